@@ -0,0 +1,581 @@
+//! Procedural macros for [`core_detect`](https://docs.rs/core_detect).
+//!
+//! Don't depend on this crate directly. Instead, enable `core_detect`'s
+//! `macros` feature, and use the macros through `core_detect::attr`. The code
+//! these macros generate refers to `::core_detect`, so it must be a dependency
+//! of the crate using them (under that name).
+
+extern crate proc_macro;
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Compiles a function several times, for different sets of target features,
+/// and dispatches to the best one at runtime.
+///
+/// ```ignore
+/// #[core_detect::attr::multiversion(targets("x86-64-v4", "x86-64-v3", "avx2+fma"))]
+/// pub fn sum(xs: &[f32]) -> f32 {
+///     xs.iter().sum()
+/// }
+/// ```
+///
+/// Each target is either a microarchitecture level (`"x86-64-v2"`,
+/// `"x86-64-v3"` or `"x86-64-v4"`), or a list of feature names separated by
+/// `+`, using the same names as `is_x86_feature_detected!`. For each target, a
+/// copy of the function is compiled with `#[target_feature(enable = ...)]` for
+/// those features, and the generated function calls the first copy whose
+/// features are all detected (so targets should be listed from best to worst).
+/// If none of them are, the function is called as written.
+///
+/// Where `rustc` names a feature differently, it's translated: `avx512gfni`,
+/// `avx512vaes` and `avx512vpclmulqdq` enable `gfni`, `vaes` and `vpclmulqdq`
+/// (so for their 512-bit forms, list `avx512f` or `avx512bw` as well), and
+/// `abm` enables `lzcnt`. Features which can be detected but have no
+/// `#[target_feature]` name at all (such as `rdtscp`, `clwb` or `pku`) are
+/// rejected, as are unknown names. The remaining names still need to be
+/// accepted by the compiler in use, which for some (like the AVX-512 ones)
+/// means a fairly recent one.
+///
+/// Detection goes through `core_detect`'s cache, so this works in `no_std`
+/// crates, and after the first call, dispatching costs a few bit tests. On
+/// architectures other than x86/x86_64, only the original function is
+/// compiled.
+///
+/// The function may be `unsafe`, but can't be generic, `const`, `async` or
+/// `extern`, can't take `self`, and its parameters must be identifiers (not
+/// patterns).
+#[proc_macro_attribute]
+pub fn multiversion(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let result = parse_targets(attr)
+        .and_then(|targets| parse_function(item.clone()).map(|f| expand(&targets, &f)));
+    match result {
+        Ok(expanded) => expanded,
+        Err(e) => {
+            // Keep the original function around, so that uses of it don't
+            // produce a flood of unrelated errors.
+            let mut out = e.into_compile_error();
+            out.extend(item);
+            out
+        }
+    }
+}
+
+struct Error {
+    span: Span,
+    message: String,
+}
+
+impl Error {
+    fn new(span: Span, message: &str) -> Self {
+        Error {
+            span,
+            message: message.into(),
+        }
+    }
+
+    /// Returns `compile_error! { "message" }`, pointing at `self.span`.
+    fn into_compile_error(self) -> TokenStream {
+        let mut message = Literal::string(&self.message);
+        message.set_span(self.span);
+        let mut bang = Punct::new('!', Spacing::Alone);
+        bang.set_span(self.span);
+        let mut body = Group::new(Delimiter::Brace, TokenTree::from(message).into());
+        body.set_span(self.span);
+        let tokens: Vec<TokenTree> = vec![
+            Ident::new("compile_error", self.span).into(),
+            bang.into(),
+            body.into(),
+        ];
+        tokens.into_iter().collect()
+    }
+}
+
+/// One of the targets in `targets(...)`.
+struct Target {
+    /// The names to pass to `is_x86_feature_detected!`.
+    features: Vec<String>,
+    /// The names to pass to `#[target_feature(enable = ...)]`.
+    enable: Vec<&'static str>,
+    span: Span,
+}
+
+/// Every name `is_x86_feature_detected!` accepts, along with the name `rustc`
+/// uses for it in `#[target_feature]`, or `None` if it has none (which is the
+/// case for instructions the compiler never emits by itself, and for features
+/// which aren't instructions at all).
+///
+/// This must list the same names as `core_detect`'s `features!` table, which
+/// its `tests/macro_feature_table.rs` checks.
+const FEATURES: &[(&str, Option<&str>)] = &[
+    ("aes", Some("aes")),
+    ("pclmulqdq", Some("pclmulqdq")),
+    ("rdrand", Some("rdrand")),
+    ("rdseed", Some("rdseed")),
+    ("tsc", None),
+    ("mmx", None),
+    ("sse", Some("sse")),
+    ("sse2", Some("sse2")),
+    ("sse3", Some("sse3")),
+    ("ssse3", Some("ssse3")),
+    ("sse4.1", Some("sse4.1")),
+    ("sse4.2", Some("sse4.2")),
+    ("sse4a", Some("sse4a")),
+    ("sha", Some("sha")),
+    ("avx", Some("avx")),
+    ("avx2", Some("avx2")),
+    ("avx512f", Some("avx512f")),
+    ("avx512cd", Some("avx512cd")),
+    ("avx512er", None),
+    ("avx512pf", None),
+    ("avx512bw", Some("avx512bw")),
+    ("avx512dq", Some("avx512dq")),
+    ("avx512vl", Some("avx512vl")),
+    ("avx512ifma", Some("avx512ifma")),
+    ("avx512vbmi", Some("avx512vbmi")),
+    ("avx512vpopcntdq", Some("avx512vpopcntdq")),
+    ("avx512vbmi2", Some("avx512vbmi2")),
+    ("avx512gfni", Some("gfni")),
+    ("avx512vaes", Some("vaes")),
+    ("avx512vpclmulqdq", Some("vpclmulqdq")),
+    ("avx512vnni", Some("avx512vnni")),
+    ("avx512bitalg", Some("avx512bitalg")),
+    ("avx512bf16", Some("avx512bf16")),
+    ("avx512vp2intersect", Some("avx512vp2intersect")),
+    ("f16c", Some("f16c")),
+    ("fma", Some("fma")),
+    ("bmi1", Some("bmi1")),
+    ("bmi2", Some("bmi2")),
+    ("abm", Some("lzcnt")),
+    ("lzcnt", Some("lzcnt")),
+    ("tbm", Some("tbm")),
+    ("popcnt", Some("popcnt")),
+    ("fxsr", Some("fxsr")),
+    ("xsave", Some("xsave")),
+    ("xsaveopt", Some("xsaveopt")),
+    ("xsaves", Some("xsaves")),
+    ("xsavec", Some("xsavec")),
+    ("cmpxchg16b", Some("cmpxchg16b")),
+    ("adx", Some("adx")),
+    ("rtm", Some("rtm")),
+    ("rdtscp", None),
+    ("rdpid", None),
+    ("serialize", None),
+    ("waitpkg", None),
+    ("hreset", None),
+    ("uintr", None),
+    ("clflush", None),
+    ("clflushopt", None),
+    ("clwb", None),
+    ("cldemote", None),
+    ("movdiri", None),
+    ("movdir64b", None),
+    ("enqcmd", None),
+    ("ptwrite", None),
+    ("wbnoinvd", None),
+    ("hle", None),
+    ("tsxldtrk", None),
+    ("shstk", None),
+    ("ibt", None),
+    ("pku", None),
+    ("ospke", None),
+    ("la57", None),
+];
+
+/// Returns the `#[target_feature]` name for the feature `name`, or an error
+/// pointing at `span` if there isn't one.
+fn target_feature_name(name: &str, span: Span) -> Result<&'static str, Error> {
+    match FEATURES.iter().find(|&&(n, _)| n == name) {
+        Some(&(_, Some(enable))) => Ok(enable),
+        Some(&(_, None)) => Err(Error::new(
+            span,
+            &format!(
+                "`{}` can be detected, but not enabled with `#[target_feature]`, \
+                 so it can't be part of a target",
+                name
+            ),
+        )),
+        None => Err(Error::new(span, &format!("unknown feature `{}`", name))),
+    }
+}
+
+/// The features enabled by each `x86-64-vN` level that we can detect (that is,
+/// excluding `movbe` and `lahfsahf`, which it's fine not to enable).
+const X86_64_V2: &[&str] = &["cmpxchg16b", "popcnt", "sse3", "sse4.1", "sse4.2", "ssse3"];
+const X86_64_V3: &[&str] = &[
+    "avx", "avx2", "bmi1", "bmi2", "f16c", "fma", "lzcnt", "xsave",
+];
+const X86_64_V4: &[&str] = &["avx512f", "avx512bw", "avx512cd", "avx512dq", "avx512vl"];
+
+fn level_features(name: &str) -> Option<Vec<&'static str>> {
+    let levels: &[&[&str]] = match name {
+        "x86-64-v2" => &[X86_64_V2],
+        "x86-64-v3" => &[X86_64_V2, X86_64_V3],
+        "x86-64-v4" => &[X86_64_V2, X86_64_V3, X86_64_V4],
+        _ => return None,
+    };
+    Some(levels.iter().flat_map(|l| l.iter().cloned()).collect())
+}
+
+/// Parses `targets("...", "...")`.
+fn parse_targets(attr: TokenStream) -> Result<Vec<Target>, Error> {
+    const USAGE: &str = r#"expected `targets("...", ...)`"#;
+    let mut tokens = attr.into_iter();
+    match tokens.next() {
+        Some(TokenTree::Ident(ref i)) if i.to_string() == "targets" => {}
+        Some(t) => return Err(Error::new(t.span(), USAGE)),
+        None => return Err(Error::new(Span::call_site(), USAGE)),
+    }
+    let list = match tokens.next() {
+        Some(TokenTree::Group(ref g)) if g.delimiter() == Delimiter::Parenthesis => g.stream(),
+        Some(t) => return Err(Error::new(t.span(), USAGE)),
+        None => return Err(Error::new(Span::call_site(), USAGE)),
+    };
+    if let Some(t) = tokens.next() {
+        return Err(Error::new(
+            t.span(),
+            "unexpected tokens after `targets(...)`",
+        ));
+    }
+
+    let mut targets = vec![];
+    for chunk in split_commas(list) {
+        let lit = match chunk.as_slice() {
+            [TokenTree::Literal(lit)] => lit,
+            [t, ..] => return Err(Error::new(t.span(), "expected a string literal")),
+            [] => unreachable!(),
+        };
+        let span = lit.span();
+        let value =
+            string_value(lit).ok_or_else(|| Error::new(span, "expected a string literal"))?;
+        let features: Vec<String> = match level_features(&value) {
+            Some(features) => features.into_iter().map(String::from).collect(),
+            None => value.split('+').map(|s| s.trim().to_string()).collect(),
+        };
+        if features.iter().any(|f| f.is_empty()) {
+            return Err(Error::new(
+                span,
+                "expected a list of features like \"avx2+fma\"",
+            ));
+        }
+        let enable = features
+            .iter()
+            .map(|f| target_feature_name(f, span))
+            .collect::<Result<Vec<_>, _>>()?;
+        targets.push(Target {
+            features,
+            enable,
+            span,
+        });
+    }
+    if targets.is_empty() {
+        return Err(Error::new(
+            Span::call_site(),
+            "expected at least one target",
+        ));
+    }
+    Ok(targets)
+}
+
+/// Returns the contents of a plain string literal (without any escapes).
+fn string_value(lit: &Literal) -> Option<String> {
+    let s = lit.to_string();
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') && !s.contains('\\') {
+        Some(s[1..s.len() - 1].to_string())
+    } else {
+        None
+    }
+}
+
+/// Splits `tokens` on commas which aren't nested inside `<...>` (other brackets
+/// are already nested inside groups). Empty chunks (from a trailing comma) are
+/// skipped.
+fn split_commas(tokens: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut chunks = vec![];
+    let mut current = vec![];
+    let mut depth = 0usize;
+    let mut prev_joint_dash = false;
+    for token in tokens {
+        let mut joint_dash = false;
+        if let TokenTree::Punct(ref p) = token {
+            match p.as_char() {
+                ',' if depth == 0 => {
+                    if !current.is_empty() {
+                        chunks.push(std::mem::take(&mut current));
+                    }
+                    continue;
+                }
+                '<' => depth += 1,
+                // Don't count the `>` in `->`.
+                '>' if !prev_joint_dash => depth = depth.saturating_sub(1),
+                '-' => joint_dash = p.spacing() == Spacing::Joint,
+                _ => {}
+            }
+        }
+        prev_joint_dash = joint_dash;
+        current.push(token);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// The parts of the function we need to take apart.
+struct Function {
+    /// Outer attributes, and the visibility.
+    prefix: Vec<TokenTree>,
+    unsafety: Option<Ident>,
+    name: Ident,
+    params: Group,
+    /// The names of the parameters, in order.
+    args: Vec<Ident>,
+    /// The tokens between the parameters and the body (that is, the return
+    /// type, if there is one).
+    ret: Vec<TokenTree>,
+    body: Group,
+}
+
+fn parse_function(item: TokenStream) -> Result<Function, Error> {
+    let mut tokens = item.into_iter().peekable();
+    let mut prefix = vec![];
+    let mut unsafety = None;
+    loop {
+        let token = match tokens.next() {
+            Some(t) => t,
+            None => return Err(Error::new(Span::call_site(), "expected a function")),
+        };
+        if let TokenTree::Ident(ref i) = token {
+            match &*i.to_string() {
+                "fn" => break,
+                "unsafe" => {
+                    unsafety = Some(i.clone());
+                    continue;
+                }
+                "const" | "async" | "extern" => {
+                    return Err(Error::new(i.span(), "`multiversion` doesn't support this"));
+                }
+                _ => {}
+            }
+        }
+        prefix.push(token);
+    }
+
+    let name = match tokens.next() {
+        Some(TokenTree::Ident(i)) => i,
+        Some(t) => return Err(Error::new(t.span(), "expected a function name")),
+        None => return Err(Error::new(Span::call_site(), "expected a function name")),
+    };
+    let params = match tokens.next() {
+        Some(TokenTree::Group(ref g)) if g.delimiter() == Delimiter::Parenthesis => g.clone(),
+        Some(TokenTree::Punct(ref p)) if p.as_char() == '<' => {
+            return Err(Error::new(
+                p.span(),
+                "`multiversion` doesn't support generic functions",
+            ));
+        }
+        Some(t) => return Err(Error::new(t.span(), "expected function parameters")),
+        None => {
+            return Err(Error::new(
+                Span::call_site(),
+                "expected function parameters",
+            ))
+        }
+    };
+    let args = parse_params(&params)?;
+
+    let mut ret = vec![];
+    let mut body = None;
+    for token in tokens {
+        if let Some(b) = body.take() {
+            ret.push(TokenTree::Group(b));
+        }
+        match token {
+            TokenTree::Group(ref g) if g.delimiter() == Delimiter::Brace => body = Some(g.clone()),
+            TokenTree::Ident(ref i) if i.to_string() == "where" => {
+                return Err(Error::new(
+                    i.span(),
+                    "`multiversion` doesn't support generic functions",
+                ));
+            }
+            t => ret.push(t),
+        }
+    }
+    let body = body.ok_or_else(|| Error::new(name.span(), "expected a function body"))?;
+
+    Ok(Function {
+        prefix,
+        unsafety,
+        name,
+        params,
+        args,
+        ret,
+        body,
+    })
+}
+
+/// Returns the names of the parameters, which must look like `name: Type` or
+/// `mut name: Type`.
+fn parse_params(params: &Group) -> Result<Vec<Ident>, Error> {
+    let mut names = vec![];
+    for param in split_commas(params.stream()) {
+        let name = match param.as_slice() {
+            [TokenTree::Ident(name), TokenTree::Punct(colon), ..]
+            | [TokenTree::Ident(_), TokenTree::Ident(name), TokenTree::Punct(colon), ..]
+                if colon.as_char() == ':' && colon.spacing() == Spacing::Alone =>
+            {
+                name
+            }
+            [t, ..] => {
+                let message = if t.to_string() == "self" || t.to_string() == "&" {
+                    "`multiversion` doesn't support methods"
+                } else {
+                    "`multiversion` only supports parameters like `name: Type`"
+                };
+                return Err(Error::new(t.span(), message));
+            }
+            [] => unreachable!(),
+        };
+        if name.to_string() == "self" {
+            return Err(Error::new(
+                name.span(),
+                "`multiversion` doesn't support methods",
+            ));
+        }
+        names.push(name.clone());
+    }
+    Ok(names)
+}
+
+fn expand(targets: &[Target], f: &Function) -> TokenStream {
+    let args = comma_separated(f.args.iter().map(|a| TokenTree::Ident(a.clone())));
+    // The wrapper takes the same parameters without any `mut`s.
+    let mut wrapper_params = TokenStream::new();
+    for (arg, ty) in f.args.iter().zip(param_types(&f.params)) {
+        wrapper_params.extend(tokens(vec![
+            arg.clone().into(),
+            Punct::new(':', Spacing::Alone).into(),
+        ]));
+        wrapper_params.extend(ty);
+        wrapper_params.extend(tokens(vec![Punct::new(',', Spacing::Alone).into()]));
+    }
+
+    let mut variants = TokenStream::new();
+    for (i, target) in targets.iter().enumerate() {
+        let clone_name = Ident::new(&format!("__core_detect_{}", i), Span::call_site());
+        let mut enable = Literal::string(&target.enable.join(","));
+        enable.set_span(target.span);
+        variants.extend(attribute(
+            "target_feature",
+            tokens(vec![
+                Ident::new("enable", Span::call_site()).into(),
+                Punct::new('=', Spacing::Alone).into(),
+                enable.into(),
+            ]),
+        ));
+        variants.extend(parse("unsafe fn"));
+        variants.extend(function_rest(clone_name.clone(), f));
+
+        let mut condition = parse("true");
+        for feature in &target.features {
+            let mut lit = Literal::string(feature);
+            lit.set_span(target.span);
+            condition.extend(parse("&& ::core_detect::is_x86_feature_detected!"));
+            condition.extend(group(Delimiter::Parenthesis, tokens(vec![lit.into()])));
+        }
+        let mut call = tokens(vec![clone_name.into()]);
+        call.extend(group(Delimiter::Parenthesis, args.clone()));
+        let mut unsafe_call = parse("return unsafe");
+        unsafe_call.extend(group(Delimiter::Brace, call));
+        unsafe_call.extend(parse(";"));
+
+        variants.extend(parse("if"));
+        variants.extend(condition);
+        variants.extend(group(Delimiter::Brace, unsafe_call));
+    }
+
+    let fallback_name = Ident::new("__core_detect_fallback", Span::call_site());
+    let mut body = attribute(
+        "cfg",
+        parse(r#"any(target_arch = "x86", target_arch = "x86_64")"#),
+    );
+    body.extend(group(Delimiter::Brace, variants));
+    if let Some(ref unsafety) = f.unsafety {
+        body.extend(tokens(vec![unsafety.clone().into()]));
+    }
+    body.extend(parse("fn"));
+    body.extend(function_rest(fallback_name.clone(), f));
+    let mut call = tokens(vec![fallback_name.into()]);
+    call.extend(group(Delimiter::Parenthesis, args));
+    if f.unsafety.is_some() {
+        body.extend(parse("unsafe"));
+        body.extend(group(Delimiter::Brace, call));
+    } else {
+        body.extend(call);
+    }
+
+    let mut out: TokenStream = f.prefix.iter().cloned().collect();
+    if let Some(ref unsafety) = f.unsafety {
+        out.extend(tokens(vec![unsafety.clone().into()]));
+    }
+    out.extend(parse("fn"));
+    out.extend(tokens(vec![f.name.clone().into()]));
+    out.extend(group(Delimiter::Parenthesis, wrapper_params));
+    out.extend(f.ret.iter().cloned());
+    out.extend(group(Delimiter::Brace, body));
+    out
+}
+
+/// Returns the types of the parameters.
+fn param_types(params: &Group) -> Vec<TokenStream> {
+    split_commas(params.stream())
+        .into_iter()
+        .map(|param| {
+            let colon = param
+                .iter()
+                .position(|t| match t {
+                    TokenTree::Punct(p) => p.as_char() == ':',
+                    _ => false,
+                })
+                .expect("checked by `parse_params`");
+            param[colon + 1..].iter().cloned().collect()
+        })
+        .collect()
+}
+
+/// Returns `name(params) -> ret { body }`, copying everything but the name
+/// from `f`.
+fn function_rest(name: Ident, f: &Function) -> TokenStream {
+    let mut out = tokens(vec![name.into(), f.params.clone().into()]);
+    out.extend(f.ret.iter().cloned());
+    out.extend(tokens(vec![f.body.clone().into()]));
+    out
+}
+
+/// Returns `#[name(args)]`.
+fn attribute(name: &str, args: TokenStream) -> TokenStream {
+    let mut inner = tokens(vec![Ident::new(name, Span::call_site()).into()]);
+    inner.extend(group(Delimiter::Parenthesis, args));
+    let mut out = parse("#");
+    out.extend(group(Delimiter::Bracket, inner));
+    out
+}
+
+fn comma_separated<I: IntoIterator<Item = TokenTree>>(items: I) -> TokenStream {
+    let mut out = TokenStream::new();
+    for item in items {
+        out.extend(tokens(vec![item, Punct::new(',', Spacing::Alone).into()]));
+    }
+    out
+}
+
+fn group(delimiter: Delimiter, stream: TokenStream) -> TokenStream {
+    tokens(vec![Group::new(delimiter, stream).into()])
+}
+
+fn tokens(tokens: Vec<TokenTree>) -> TokenStream {
+    tokens.into_iter().collect()
+}
+
+fn parse(s: &str) -> TokenStream {
+    s.parse().expect("invalid tokens")
+}
@@ -90,6 +90,28 @@ features! {
     /// * `"cmpxchg16b"`
     /// * `"adx"`
     /// * `"rtm"`
+    /// * `"rdtscp"`
+    /// * `"rdpid"`
+    /// * `"serialize"`
+    /// * `"waitpkg"`
+    /// * `"hreset"`
+    /// * `"uintr"`
+    /// * `"clflush"`
+    /// * `"clflushopt"`
+    /// * `"clwb"`
+    /// * `"cldemote"`
+    /// * `"movdiri"`
+    /// * `"movdir64b"`
+    /// * `"enqcmd"`
+    /// * `"ptwrite"`
+    /// * `"wbnoinvd"`
+    /// * `"hle"`
+    /// * `"tsxldtrk"`
+    /// * `"shstk"`
+    /// * `"ibt"`
+    /// * `"pku"`
+    /// * `"ospke"`
+    /// * `"la57"`
     ///
     /// [docs]: https://software.intel.com/sites/landingpage/IntrinsicsGuide
     @BIND_FEATURE_NAME: "abm"; "lzcnt"; // abm is a synonym for lzcnt
@@ -157,7 +179,7 @@ features! {
     @FEATURE: avx512vnni: "avx512vnni";
     /// AVX-512 VNNI (Vector Neural Network Instructions)
     @FEATURE: avx512bitalg: "avx512bitalg";
-    /// AVX-512 BITALG (Support for VPOPCNT[B,W] and VPSHUFBITQMB)
+    /// AVX-512 BITALG (Support for `VPOPCNT[B,W]` and `VPSHUFBITQMB`)
     @FEATURE: avx512bf16: "avx512bf16";
     /// AVX-512 BF16 (BFLOAT16 instructions)
     @FEATURE: avx512vp2intersect: "avx512vp2intersect";
@@ -192,4 +214,52 @@ features! {
     /// ADX, Intel ADX (Multi-Precision Add-Carry Instruction Extensions)
     @FEATURE: rtm: "rtm";
     /// RTM, Intel (Restricted Transactional Memory)
+    ///
+    /// Not reported if the CPU advertises `RTM_ALWAYS_ABORT`.
+    @FEATURE: rdtscp: "rdtscp";
+    /// RDTSCP (Read Time-Stamp Counter and Processor ID)
+    @FEATURE: rdpid: "rdpid";
+    /// RDPID (Read Processor ID)
+    @FEATURE: serialize: "serialize";
+    /// SERIALIZE (Serialize Instruction Execution)
+    @FEATURE: waitpkg: "waitpkg";
+    /// WAITPKG (UMONITOR, UMWAIT and TPAUSE)
+    @FEATURE: hreset: "hreset";
+    /// HRESET (History Reset)
+    @FEATURE: uintr: "uintr";
+    /// UINTR (User Interrupts)
+    @FEATURE: clflush: "clflush";
+    /// CLFLUSH (Flush Cache Line)
+    @FEATURE: clflushopt: "clflushopt";
+    /// CLFLUSHOPT (Flush Cache Line Optimized)
+    @FEATURE: clwb: "clwb";
+    /// CLWB (Cache Line Write Back)
+    @FEATURE: cldemote: "cldemote";
+    /// CLDEMOTE (Cache Line Demote)
+    @FEATURE: movdiri: "movdiri";
+    /// MOVDIRI (Move Doubleword as Direct Store)
+    @FEATURE: movdir64b: "movdir64b";
+    /// MOVDIR64B (Move 64 Bytes as Direct Store)
+    @FEATURE: enqcmd: "enqcmd";
+    /// ENQCMD (Enqueue Command)
+    @FEATURE: ptwrite: "ptwrite";
+    /// PTWRITE (Write Data to a Processor Trace Packet)
+    @FEATURE: wbnoinvd: "wbnoinvd";
+    /// WBNOINVD (Write Back and Do Not Invalidate Cache)
+    @FEATURE: hle: "hle";
+    /// HLE, Intel (Hardware Lock Elision)
+    @FEATURE: tsxldtrk: "tsxldtrk";
+    /// TSXLDTRK, Intel (TSX Suspend Load Address Tracking)
+    @FEATURE: shstk: "shstk";
+    /// CET-SS (Control-flow Enforcement Technology Shadow Stack)
+    @FEATURE: ibt: "ibt";
+    /// CET-IBT (Control-flow Enforcement Technology Indirect Branch Tracking)
+    @FEATURE: pku: "pku";
+    /// PKU (Memory Protection Keys for Userspace)
+    ///
+    /// Only reported if the OS has enabled protection keys (see `ospke`).
+    @FEATURE: ospke: "ospke";
+    /// OSPKE (OS has enabled Protection Keys)
+    @FEATURE: la57: "la57";
+    /// LA57 (57-bit Linear Addresses / 5-Level Paging)
 }
@@ -3,37 +3,50 @@
 
 #![allow(dead_code)] // not used on all platforms
 
-use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(any(feature = "critical-section", core_detect_unsafe_single_threaded)))]
+mod atomic;
+#[cfg(not(any(feature = "critical-section", core_detect_unsafe_single_threaded)))]
+use self::atomic as storage;
+
+#[cfg(any(feature = "critical-section", core_detect_unsafe_single_threaded))]
+mod cell;
+#[cfg(any(feature = "critical-section", core_detect_unsafe_single_threaded))]
+use self::cell as storage;
 
 /// Sets the `bit` of `x`.
 #[inline]
-const fn set_bit(x: u64, bit: u32) -> u64 {
+const fn set_bit(x: u128, bit: u32) -> u128 {
     x | 1 << bit
 }
 
 /// Tests the `bit` of `x`.
 #[inline]
-const fn test_bit(x: u64, bit: u32) -> bool {
+const fn test_bit(x: u128, bit: u32) -> bool {
     x & (1 << bit) != 0
 }
 
 /// Maximum number of features that can be cached.
-const CACHE_CAPACITY: u32 = 62;
+const CACHE_CAPACITY: u32 = 93;
 
 /// This type is used to initialize the cache
-#[derive(Copy, Clone)]
-pub(crate) struct Initializer(u64);
-
-#[allow(clippy::use_self)]
-impl Default for Initializer {
-    fn default() -> Self {
-        Initializer(0)
-    }
-}
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub(crate) struct Initializer(u128);
 
 // NOTE: the `debug_assert!` would catch that we do not add more Features than
 // the one fitting our cache.
 impl Initializer {
+    /// Returns an `Initializer` with exactly the bits in `bits` set.
+    #[inline]
+    pub(crate) const fn from_bits(bits: u128) -> Initializer {
+        Initializer(bits)
+    }
+
+    /// Returns the bits which are set.
+    #[inline]
+    pub(crate) const fn bits(self) -> u128 {
+        self.0
+    }
+
     /// Tests the `bit` of the cache.
     #[inline]
     pub(crate) fn test(self, bit: u32) -> bool {
@@ -54,61 +67,26 @@ impl Initializer {
         let v = self.0;
         self.0 = set_bit(v, bit);
     }
-}
-
-/// This global variable is a cache of the features supported by the CPU.
-// Note: on x64, we only use the first slot
-static CACHE: [Cache; 2] = [Cache::uninitialized(), Cache::uninitialized()];
 
-/// Feature cache with capacity for `size_of::<usize::MAX>() * 8 - 1` features.
-///
-/// Note: 0 is used to represent an uninitialized cache, and (at least) the most
-/// significant bit is set on any cache which has been initialized.
-///
-/// Note: we use `Relaxed` atomic operations, because we are only interested in
-/// the effects of operations on a single memory location. That is, we only need
-/// "modification order", and not the full-blown "happens before".
-struct Cache(AtomicUsize);
-
-impl Cache {
-    const CAPACITY: u32 = (core::mem::size_of::<usize>() * 8 - 1) as u32;
-    const MASK: usize = (1 << Cache::CAPACITY) - 1;
-    const INITIALIZED_BIT: usize = 1usize << Cache::CAPACITY;
-
-    /// Creates an uninitialized cache.
-    #[allow(clippy::declare_interior_mutable_const)]
-    const fn uninitialized() -> Self {
-        Cache(AtomicUsize::new(0))
+    /// Returns the bits set in both `self` and `other`.
+    #[inline]
+    pub(crate) fn and(self, other: Initializer) -> Initializer {
+        Initializer(self.0 & other.0)
     }
 
-    /// Is the `bit` in the cache set? Returns `None` if the cache has not been initialized.
+    /// Returns the bits set in either `self` or `other`.
     #[inline]
-    pub(crate) fn test(&self, bit: u32) -> Option<bool> {
-        let cached = self.0.load(Ordering::Relaxed);
-        if cached == 0 {
-            None
-        } else {
-            Some(test_bit(cached as u64, bit))
-        }
+    pub(crate) fn or(self, other: Initializer) -> Initializer {
+        Initializer(self.0 | other.0)
     }
 
-    /// Initializes the cache.
+    /// Returns the bits set in `self` but not in `other`.
     #[inline]
-    fn initialize(&self, value: usize) -> usize {
-        debug_assert_eq!((value & !Cache::MASK), 0);
-        self.0
-            .store(value | Cache::INITIALIZED_BIT, Ordering::Relaxed);
-        value
+    pub(crate) fn and_not(self, other: Initializer) -> Initializer {
+        Initializer(self.0 & !other.0)
     }
 }
 
-#[inline]
-fn initialize(value: Initializer) -> Initializer {
-    CACHE[0].initialize((value.0) as usize & Cache::MASK);
-    CACHE[1].initialize((value.0 >> Cache::CAPACITY) as usize & Cache::MASK);
-    value
-}
-
 // We only have to detect features once, and it's fairly costly, so hint to LLVM
 // that it should assume that cache hits are more common than misses (which is
 // the point of caching). It's possibly unfortunate that this function needs to
@@ -120,29 +98,77 @@ fn initialize(value: Initializer) -> Initializer {
 // cache again.
 #[cold]
 fn detect_and_initialize() -> Initializer {
-    initialize(super::os::detect_features())
+    storage::store(super::os::detect_features(), true)
 }
 
+/// Fills the cache before `main` runs, with the `eager_init` feature.
+///
+/// A constructor in an rlib only runs if the linker pulls in its object file,
+/// which it only does if that object defines a symbol the binary uses. So this
+/// lives next to `get`, which every inlined call to `test` refers to, so that
+/// it's linked in whenever the cache is used (`tests/eager_init.rs` checks
+/// this).
+#[cfg(all(
+    feature = "eager_init",
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "dragonfly",
+        target_os = "illumos",
+        target_os = "solaris",
+    ),
+))]
+#[used]
+#[link_section = ".init_array"]
+static EAGER_INIT: extern "C" fn() = {
+    extern "C" fn eager_init() {
+        get();
+    }
+    eager_init
+};
+
 /// Tests the `bit` of the storage. If the storage has not been initialized,
 /// initializes it with the result of `os::detect_features()`.
 ///
 /// On its first invocation, it detects the CPU features and caches them in the
-/// `CACHE` global variable as an `AtomicU64`.
+/// `CACHE` global variable (as an array of `AtomicUsize`, unless another
+/// backend was selected).
 ///
 /// It uses the `Feature` variant to index into this variable as a bitset. If
 /// the bit is set, the feature is enabled, and otherwise it is disabled.
-///
-/// If the feature `std_detect_env_override` is enabled looks for the env
-/// variable `RUST_STD_DETECT_UNSTABLE` and uses its its content to disable
-/// Features that would had been otherwise detected.
 #[inline]
 pub(crate) fn test(bit: u32) -> bool {
-    let (relative_bit, idx) = if bit < Cache::CAPACITY {
-        (bit, 0)
-    } else {
-        (bit - Cache::CAPACITY, 1)
-    };
-    CACHE[idx]
-        .test(relative_bit)
-        .unwrap_or_else(|| detect_and_initialize().test(bit))
+    storage::test(bit).unwrap_or_else(|| get().test(bit))
+}
+
+/// Returns every bit of the storage, initializing it (as in `test`) if needed.
+///
+/// The result is a consistent snapshot: it's always the value from a single
+/// detection. If another thread is updating the storage, the atomic backend
+/// doesn't wait for it, and this detects features itself as on a cache miss.
+pub(crate) fn get() -> Initializer {
+    match storage::load() {
+        Some(value) => value,
+        None => detect_and_initialize(),
+    }
+}
+
+/// Returns true if the storage has been initialized.
+pub(crate) fn is_initialized() -> bool {
+    storage::load().is_some()
+}
+
+/// Detects features again, and replaces the contents of the storage with the
+/// result (as a single update, as far as `get` is concerned).
+pub(crate) fn redetect() -> Initializer {
+    storage::store(super::os::detect_features(), false)
+}
+
+/// Returns the storage to its uninitialized state, so that the next call to
+/// `test` or `get` detects features again.
+pub(crate) fn reset() {
+    storage::clear()
 }
@@ -0,0 +1,187 @@
+//! The default cache backend, which stores the cache in atomics.
+
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+
+use super::{test_bit, Initializer};
+
+/// This global variable is a cache of the features supported by the CPU.
+// Note: on x64, we only use the first two slots
+static CACHE: [Cache; 3] = [
+    Cache::uninitialized(),
+    Cache::uninitialized(),
+    Cache::uninitialized(),
+];
+
+/// Sequence number for `CACHE`, which lets us read all of its slots at once
+/// consistently, as a seqlock.
+///
+/// Writers make this odd while they update `CACHE`, and even again (and larger
+/// than before) once they're done. A reader which sees the same even value
+/// before and after reading the slots knows that it saw a single write.
+///
+/// Nothing ever waits for `SEQUENCE` to change, since the thread which made it
+/// odd could be the one waiting (if the cache is used from a signal handler,
+/// say). Readers which race with a writer treat the cache as uninitialized
+/// instead, and writers which race with another writer give up.
+static SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+
+/// Feature cache with capacity for `size_of::<usize::MAX>() * 8 - 1` features.
+///
+/// Note: 0 is used to represent an uninitialized cache, and (at least) the most
+/// significant bit is set on any cache which has been initialized.
+///
+/// Note: testing a single bit uses a `Relaxed` load of a single slot, because
+/// we're only interested in the value of that one memory location. Anything
+/// which needs more than one slot goes through `SEQUENCE` instead.
+struct Cache(AtomicUsize);
+
+impl Cache {
+    const CAPACITY: u32 = (core::mem::size_of::<usize>() * 8 - 1) as u32;
+    const MASK: usize = (1 << Cache::CAPACITY) - 1;
+    const INITIALIZED_BIT: usize = 1usize << Cache::CAPACITY;
+
+    /// Creates an uninitialized cache.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const fn uninitialized() -> Self {
+        Cache(AtomicUsize::new(0))
+    }
+
+    /// Is the `bit` in the cache set? Returns `None` if the cache has not been initialized.
+    #[inline]
+    pub(crate) fn test(&self, bit: u32) -> Option<bool> {
+        let cached = self.0.load(Ordering::Relaxed);
+        if cached == 0 {
+            None
+        } else {
+            Some(test_bit(cached as u128, bit))
+        }
+    }
+
+    /// Returns the raw value of the cache (including `INITIALIZED_BIT`).
+    #[inline]
+    fn load(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns the cache to its uninitialized state.
+    #[inline]
+    fn clear(&self) {
+        self.0.store(0, Ordering::Relaxed);
+    }
+
+    /// Initializes the cache.
+    #[inline]
+    fn initialize(&self, value: usize) -> usize {
+        debug_assert_eq!((value & !Cache::MASK), 0);
+        self.0
+            .store(value | Cache::INITIALIZED_BIT, Ordering::Relaxed);
+        value
+    }
+}
+
+/// Loads every slot of `CACHE`, returning `None` if it hasn't been initialized.
+///
+/// Must only be called while holding the seqlock, or as part of a read which is
+/// validated against `SEQUENCE`.
+#[inline]
+fn load_slots() -> Option<Initializer> {
+    let mut value = 0u128;
+    for (i, cache) in CACHE.iter().enumerate() {
+        let bits = cache.load();
+        if bits == 0 {
+            return None;
+        }
+        value |= ((bits & Cache::MASK) as u128) << (i as u32 * Cache::CAPACITY);
+    }
+    Some(Initializer::from_bits(value))
+}
+
+/// Runs `f` while holding the seqlock (that is, while `SEQUENCE` is odd), so
+/// that it can update `CACHE` without readers seeing a partial update.
+///
+/// Returns `None` without running `f` if another write is in progress.
+fn try_write<R, F: FnOnce() -> R>(f: F) -> Option<R> {
+    // Take the seqlock by making `SEQUENCE` odd.
+    let seq = SEQUENCE.load(Ordering::Relaxed);
+    if seq & 1 != 0
+        || SEQUENCE
+            .compare_exchange(
+                seq,
+                seq.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_err()
+    {
+        return None;
+    }
+    // Ensure readers which see any of the stores in `f` also see `SEQUENCE` as
+    // odd (or newer).
+    fence(Ordering::Release);
+
+    let result = f();
+
+    SEQUENCE.store(seq.wrapping_add(2), Ordering::Release);
+    Some(result)
+}
+
+/// Stores `value` in the cache, unless `only_if_uninitialized` is true and the
+/// cache has already been initialized. Returns the value in the cache after
+/// this, or `value` if another thread was writing to the cache, in which case
+/// `value` isn't stored.
+pub(super) fn store(value: Initializer, only_if_uninitialized: bool) -> Initializer {
+    try_write(|| match load_slots() {
+        Some(current) if only_if_uninitialized => current,
+        _ => {
+            CACHE[0].initialize(value.bits() as usize & Cache::MASK);
+            CACHE[1].initialize((value.bits() >> Cache::CAPACITY) as usize & Cache::MASK);
+            CACHE[2].initialize((value.bits() >> (2 * Cache::CAPACITY)) as usize & Cache::MASK);
+            value
+        }
+    })
+    .unwrap_or(value)
+}
+
+/// Returns the cache to its uninitialized state, unless another thread is
+/// writing to it.
+pub(super) fn clear() {
+    let _ = try_write(|| {
+        for cache in &CACHE {
+            cache.clear();
+        }
+    });
+}
+
+/// Tests the `bit` of the cache. Returns `None` if the cache has not been
+/// initialized.
+#[inline]
+pub(super) fn test(bit: u32) -> Option<bool> {
+    let (relative_bit, idx) = if bit < Cache::CAPACITY {
+        (bit, 0)
+    } else if bit < 2 * Cache::CAPACITY {
+        (bit - Cache::CAPACITY, 1)
+    } else {
+        (bit - 2 * Cache::CAPACITY, 2)
+    };
+    CACHE[idx].test(relative_bit)
+}
+
+/// Returns every bit of the cache, or `None` if it has not been initialized or
+/// another thread is writing to it.
+///
+/// The result is a consistent snapshot: it's always the value from a single
+/// call to `store`.
+pub(super) fn load() -> Option<Initializer> {
+    let seq = SEQUENCE.load(Ordering::Acquire);
+    if seq & 1 != 0 {
+        // A write is in progress.
+        return None;
+    }
+    let value = load_slots();
+    fence(Ordering::Acquire);
+    if SEQUENCE.load(Ordering::Relaxed) == seq {
+        value
+    } else {
+        None
+    }
+}
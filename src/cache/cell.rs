@@ -0,0 +1,71 @@
+//! A cache backend for targets without atomics, selected by the
+//! `critical-section` feature or the `core_detect_unsafe_single_threaded` cfg.
+//!
+//! With `critical-section`, every access to the cache happens inside
+//! `critical_section::with`. With only `core_detect_unsafe_single_threaded`,
+//! the cache is accessed without any synchronization at all, which is only
+//! sound if it's never accessed from more than one thread (or interrupt
+//! handler) at a time.
+
+use core::cell::UnsafeCell;
+
+use super::Initializer;
+
+/// This global variable is a cache of the features supported by the CPU, or
+/// `None` if it hasn't been initialized.
+static CACHE: Storage = Storage(UnsafeCell::new(None));
+
+struct Storage(UnsafeCell<Option<Initializer>>);
+
+// Safety: All accesses go through `with`, which either holds a critical
+// section, or relies on the promise (made by whoever builds the final binary
+// with `--cfg core_detect_unsafe_single_threaded`) that there's only one
+// thread.
+unsafe impl Sync for Storage {}
+
+#[cfg(feature = "critical-section")]
+#[inline]
+fn with<R, F: FnOnce(&mut Option<Initializer>) -> R>(f: F) -> R {
+    // Safety: Nobody else can access the cache while we hold the critical
+    // section.
+    critical_section::with(|_| f(unsafe { &mut *CACHE.0.get() }))
+}
+
+#[cfg(not(feature = "critical-section"))]
+#[inline]
+fn with<R, F: FnOnce(&mut Option<Initializer>) -> R>(f: F) -> R {
+    // Safety: The `core_detect_unsafe_single_threaded` cfg is a promise that
+    // nobody else can access the cache, and `f` can't reenter this function.
+    f(unsafe { &mut *CACHE.0.get() })
+}
+
+/// Tests the `bit` of the cache. Returns `None` if the cache has not been
+/// initialized.
+#[inline]
+pub(super) fn test(bit: u32) -> Option<bool> {
+    with(|cache| cache.map(|value| value.test(bit)))
+}
+
+/// Returns every bit of the cache, or `None` if it has not been initialized.
+#[inline]
+pub(super) fn load() -> Option<Initializer> {
+    with(|cache| *cache)
+}
+
+/// Stores `value` in the cache, unless `only_if_uninitialized` is true and the
+/// cache has already been initialized. Returns the value in the cache after
+/// this.
+pub(super) fn store(value: Initializer, only_if_uninitialized: bool) -> Initializer {
+    with(|cache| match *cache {
+        Some(current) if only_if_uninitialized => current,
+        _ => {
+            *cache = Some(value);
+            value
+        }
+    })
+}
+
+/// Returns the cache to its uninitialized state.
+pub(super) fn clear() {
+    with(|cache| *cache = None)
+}
@@ -0,0 +1,250 @@
+//! A set of CPU features, for APIs which report several features at once.
+
+use core::fmt;
+use core::iter::FromIterator;
+
+use crate::{cache, Feature};
+
+/// A set of CPU features.
+///
+/// Features are named the same way as they are for
+/// `is_x86_feature_detected!`. On architectures other than x86/x86_64, this is
+/// always empty.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct FeatureSet(cache::Initializer);
+
+impl FeatureSet {
+    /// Returns a set with no features in it.
+    #[inline]
+    pub fn empty() -> Self {
+        FeatureSet::default()
+    }
+
+    #[inline]
+    pub(crate) const fn from_initializer(value: cache::Initializer) -> Self {
+        FeatureSet(value)
+    }
+
+    #[inline]
+    pub(crate) fn has(&self, f: Feature) -> bool {
+        self.0.test(f as u32)
+    }
+
+    #[inline]
+    pub(crate) fn insert(&mut self, f: Feature) {
+        self.0.set(f as u32)
+    }
+
+    /// Returns true if `feature` (e.g. `"avx2"`) is in the set. Unknown
+    /// feature names are never in the set.
+    #[inline]
+    pub fn contains(&self, feature: &str) -> bool {
+        match Feature::from_name(feature) {
+            Some(f) => self.has(f),
+            None => false,
+        }
+    }
+
+    /// Returns true if the set contains no features.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        *self == FeatureSet::empty()
+    }
+
+    /// Returns the number of features in the set.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns the features in both `self` and `other`.
+    #[inline]
+    pub fn intersection(&self, other: &FeatureSet) -> FeatureSet {
+        FeatureSet(self.0.and(other.0))
+    }
+
+    /// Returns the features in either `self` or `other`.
+    #[inline]
+    pub fn union(&self, other: &FeatureSet) -> FeatureSet {
+        FeatureSet(self.0.or(other.0))
+    }
+
+    /// Returns the features in `self` which aren't in `other`.
+    #[inline]
+    pub fn difference(&self, other: &FeatureSet) -> FeatureSet {
+        FeatureSet(self.0.and_not(other.0))
+    }
+
+    /// Returns an iterator over the names of the features in the set.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &'static str> {
+        let set = *self;
+        Feature::ALL
+            .iter()
+            .filter(move |&&f| set.has(f))
+            .map(|&f| f.name())
+    }
+}
+
+impl FromIterator<Feature> for FeatureSet {
+    fn from_iter<I: IntoIterator<Item = Feature>>(iter: I) -> Self {
+        let mut set = FeatureSet::empty();
+        for f in iter {
+            set.insert(f);
+        }
+        set
+    }
+}
+
+/// Returns the features which were enabled at compile time (with `-C
+/// target-feature` or `-C target-cpu`), and which the compiler may therefore
+/// have used anywhere in the program.
+///
+/// Only features which can be enabled with `#[target_feature]` can be in this
+/// set, so it never contains things like `"tsc"` or `"ospke"`.
+#[inline]
+pub const fn compile_time_features() -> FeatureSet {
+    FeatureSet::from_initializer(cache::Initializer::from_bits(Feature::COMPILE_TIME_BITS))
+}
+
+/// Returns every feature detected on the current CPU, detecting them first if
+/// that hasn't happened yet.
+///
+/// This reads the same cache as `is_x86_feature_detected!`, but all at once,
+/// so the result always comes from a single detection (even if another thread
+/// is concurrently updating the cache). Unlike the macro, features which were
+/// enabled at compile time are only included if they were actually detected.
+#[inline]
+pub fn snapshot() -> FeatureSet {
+    FeatureSet::from_initializer(cache::get())
+}
+
+/// Returns the features which were enabled at compile time, but which aren't
+/// supported by the current CPU (or OS), or an empty set if there are none.
+///
+/// If this isn't empty, the program is likely to crash with an illegal
+/// instruction fault, so it can be used to report a clear error at startup
+/// instead:
+///
+/// ```
+/// let missing = core_detect::missing_at_runtime();
+/// if !missing.is_empty() {
+///     eprintln!("error: this CPU doesn't support {:?}", missing);
+///     std::process::exit(1);
+/// }
+/// ```
+///
+/// Note that `is_x86_feature_detected!` always returns true for features
+/// enabled at compile time, without checking the CPU, so it can't be used for
+/// this.
+#[inline]
+pub fn missing_at_runtime() -> FeatureSet {
+    compile_time_features().difference(&snapshot())
+}
+
+/// Checks that the CPU supports every feature enabled at compile time (that is,
+/// that [`missing_at_runtime`] is empty), and returns an error describing the
+/// missing ones otherwise.
+///
+/// See [`ensure_compile_time_features`] for a version which reports the error
+/// and exits.
+#[inline]
+pub fn check_compile_time_features() -> Result<(), MissingFeatures> {
+    let missing = missing_at_runtime();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(MissingFeatures::new(compile_time_features(), missing))
+    }
+}
+
+/// Checks that the CPU supports every feature enabled at compile time (that is,
+/// that [`missing_at_runtime`] is empty), and reports an error otherwise, such
+/// as:
+///
+/// ```text
+/// this binary requires fxsr, sse, sse2, avx2, fma; this CPU lacks avx2, fma
+/// ```
+///
+/// On Linux with the `linux_syscalls` feature, the error is written to stderr
+/// with a raw `write` system call, and the process exits with status 1 (see
+/// also `ensure_compile_time_features_at_startup!`, which calls this before
+/// `main`). Otherwise, this panics with that message.
+///
+/// Note that this can only help if it runs before the compiler has used any of
+/// the missing features, and it's compiled with the same features as the rest
+/// of the program. It sticks to simple code in the hope that the compiler won't
+/// use them here, but this isn't guaranteed.
+pub fn ensure_compile_time_features() {
+    let report = match check_compile_time_features() {
+        Ok(()) => return,
+        Err(report) => report,
+    };
+    #[cfg(all(
+        feature = "linux_syscalls",
+        target_os = "linux",
+        any(target_arch = "x86", target_arch = "x86_64"),
+    ))]
+    crate::linux::exit_with_message(&report);
+    #[cfg(not(all(
+        feature = "linux_syscalls",
+        target_os = "linux",
+        any(target_arch = "x86", target_arch = "x86_64"),
+    )))]
+    panic!("{}", report);
+}
+
+/// The error returned by [`check_compile_time_features`].
+///
+/// Its `Display` implementation produces the message that
+/// [`ensure_compile_time_features`] reports.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MissingFeatures {
+    required: FeatureSet,
+    missing: FeatureSet,
+}
+
+impl MissingFeatures {
+    /// Returns an error saying that `missing` (out of the `required`
+    /// features) aren't supported.
+    #[inline]
+    pub fn new(required: FeatureSet, missing: FeatureSet) -> Self {
+        MissingFeatures { required, missing }
+    }
+
+    /// The features which were enabled at compile time.
+    #[inline]
+    pub fn required(&self) -> FeatureSet {
+        self.required
+    }
+
+    /// The required features which the CPU doesn't support.
+    #[inline]
+    pub fn missing(&self) -> FeatureSet {
+        self.missing
+    }
+}
+
+impl fmt::Display for MissingFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn list(f: &mut fmt::Formatter, set: &FeatureSet) -> fmt::Result {
+            for (i, name) in set.iter().enumerate() {
+                if i != 0 {
+                    f.write_str(", ")?;
+                }
+                f.write_str(name)?;
+            }
+            Ok(())
+        }
+        f.write_str("this binary requires ")?;
+        list(f, &self.required)?;
+        f.write_str("; this CPU lacks ")?;
+        list(f, &self.missing)
+    }
+}
+
+impl fmt::Debug for FeatureSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
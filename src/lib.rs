@@ -28,6 +28,37 @@
 //! (In the future, this crate may provide another macro which returns false in
 //! these cases instead, and supports testing multiple features simultaneously).
 //!
+//! To pick between several implementations of a function based on which
+//! features are available, see [`multiversion!`] (or [`ifunc!`], which only
+//! checks once, and caches the choice as a function pointer). With the `macros`
+//! feature enabled, `attr::multiversion` does the same for a single function
+//! body, compiling it once for each set of features.
+//!
+//! # Other CPU information
+//!
+//! On x86/x86_64, this crate also provides functions for information that
+//! doesn't fit into a single feature flag, such as [`xsave_info`],
+//! [`address_sizes`], [`mitigation_caps`], [`pmu_info`], [`power_features`],
+//! [`sgx_info`], [`sev_info`] and [`rdt_info`]. Unlike the macro, these aren't
+//! cached, and query the CPU on every call (each one's documentation lists the
+//! `cpuid` leaves it reads).
+//!
+//! # Targets without atomics
+//!
+//! By default, the cache of detected features is stored in atomics. For targets
+//! without them, it can be stored in a plain static instead, guarded by the
+//! [`critical-section`](https://docs.rs/critical-section) crate, by enabling
+//! the `critical-section` feature. The final binary then needs to provide a
+//! critical section implementation, as described in that crate's docs.
+//!
+//! Alternatively, building with `RUSTFLAGS="--cfg
+//! core_detect_unsafe_single_threaded"` stores it in a static which isn't
+//! guarded at all. **This is only sound if this crate is never used from more
+//! than one thread or interrupt handler at once**, which only whoever builds
+//! the final binary can know. That's why it's a `--cfg` flag rather than a
+//! Cargo feature: any crate in the dependency graph could enable a feature.
+//! If both are used, the `critical-section` feature takes precedence.
+//!
 //! # Caveats
 //! The `cpuid` instruction doesn't exist on all x86 machines, it was added
 //! around 1994. (It's also not available on SGX, but this doesn't cause any
@@ -60,14 +91,14 @@
 //!    opposite of the last one — assume `has_cpuid` would have returned true,
 //!    and call `cpuid` anyway.
 //!
-//!     In practice, this should be fine. These machines are rare now (they're
-//!     over 30 years old...), and pretty only are common through QEMU, and even
-//!     then, usually after a misconfiguration.
+//!    In practice, this should be fine. These machines are rare now (they're
+//!    over 30 years old...), and pretty only are common through QEMU, and even
+//!    then, usually after a misconfiguration.
 //!
-//!     If you do happen to run the instruction, the process crashes, but in a
-//!     controlled manner — Executing an illegal instruction to tringger a
-//!     SIGILL is what `core::intrinsics::abort` does on x86, so it's not
-//!     dangerous or anything.
+//!    If you do happen to run the instruction, the process crashes, but in a
+//!    controlled manner — Executing an illegal instruction to tringger a
+//!    SIGILL is what `core::intrinsics::abort` does on x86, so it's not
+//!    dangerous or anything.
 //!
 //! 3. Using unstable nightly features (`feature = "unstable_has_cpuid"`): This
 //!    approach requires a nightly compiler, but has no other major downsides,
@@ -90,6 +121,14 @@
 #[macro_use]
 mod macros;
 
+mod multiversion;
+
+/// Attribute macros, enabled by the `macros` feature.
+#[cfg(feature = "macros")]
+pub mod attr {
+    pub use core_detect_macros::multiversion;
+}
+
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 #[path = "arch/x86.rs"]
 #[macro_use]
@@ -98,8 +137,11 @@ mod arch;
 // Unimplemented architecture:
 #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
 mod arch {
-    #[doc(hidden)]
-    pub(crate) enum Feature {
+    /// A CPU feature which can be detected at runtime. No features are
+    /// supported on this architecture.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub enum Feature {
+        #[doc(hidden)]
         Null,
     }
 
@@ -107,20 +149,84 @@ mod arch {
     pub mod __is_feature_detected {}
 
     impl Feature {
-        #[doc(hidden)]
-        pub(crate) fn from_str(_s: &str) -> Result<Feature, ()> {
-            Err(())
-        }
-        #[doc(hidden)]
-        pub(crate) fn to_str(self) -> &'static str {
+        pub(crate) const ALL: &'static [Feature] = &[];
+        pub(crate) const COMPILE_TIME_BITS: u128 = 0;
+
+        /// Returns the name of this feature.
+        pub fn name(self) -> &'static str {
             ""
         }
+
+        maybe_const_fn! {
+            /// Returns the feature named `name`, which is always `None` on
+            /// this architecture.
+            pub fn from_name(_name: &str) -> Option<Feature> {
+                None
+            }
+        }
     }
 }
 
-pub(crate) use crate::arch::Feature;
 #[doc(hidden)]
 pub use crate::arch::__is_feature_detected;
+pub use crate::arch::Feature;
+
+/// Detects CPU features and fills the cache used by `is_x86_feature_detected!`,
+/// if that hasn't happened yet.
+///
+/// This is never required, since the cache is filled on first use, but it
+/// lets you move the cost of detection (a few dozen `cpuid` instructions) out
+/// of a latency-sensitive path. With the `eager_init` feature, this is done by
+/// a constructor before `main` on ELF targets, but calling it is still useful
+/// where constructors don't run (such as in kernels and bootloaders).
+#[inline]
+pub fn initialize() {
+    cache::get();
+}
+
+/// Returns true if the cache has been filled. Only for testing `eager_init`.
+#[cfg(feature = "__test_hooks")]
+#[doc(hidden)]
+pub fn __cache_is_initialized() -> bool {
+    cache::is_initialized()
+}
+
+/// Detects CPU features again, and replaces the contents of the cache used by
+/// `is_x86_feature_detected!` with the result, which is also returned.
+///
+/// This is for when the features available may have changed, such as after a
+/// virtual machine is live-migrated to a different host.
+///
+/// # Concurrency
+///
+/// The cache is replaced all at once: [`snapshot`] (and [`missing_at_runtime`])
+/// never see a mix of old and new values. Each use of `is_x86_feature_detected!`
+/// also sees a single value of that feature, but several uses in a row may see
+/// different versions, if they race with this.
+///
+/// Nothing waits for this to finish, so the cache can still be used from a
+/// signal handler which interrupts it. Readers which race with it detect
+/// features themselves instead of reading the cache, and if another thread is
+/// updating the cache at the same time, this result isn't stored.
+///
+/// Only the cache is updated, though. Any decision already made from it, such
+/// as the implementation chosen by an [`ifunc!`] function, is not.
+#[inline]
+pub fn redetect() -> FeatureSet {
+    FeatureSet::from_initializer(cache::redetect())
+}
+
+/// Empties the cache used by `is_x86_feature_detected!`, so that features are
+/// detected again the next time they're needed.
+///
+/// This is mostly useful for tests. Like with [`redetect`], concurrent readers
+/// see either the old contents of the cache, or newly detected ones (if
+/// several threads need to detect features at the same time, they may each do
+/// so, but only one result is stored), and nothing waits for this to finish.
+#[inline]
+pub fn reset_cache() {
+    cache::reset()
+}
 
 /// Performs run-time feature detection.
 #[inline]
@@ -133,6 +239,29 @@ fn check_for(x: Feature) -> bool {
 #[path = "os/x86.rs"]
 mod os;
 
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub use crate::os::{
+    address_sizes, clflush_line_size, core_type, core_type_and_model_id, is_tdx_guest,
+    mitigation_caps, pmu_info, power_features, rdt_info, sev_info, sgx_info, xsave_info,
+    AmdMbaInfo, CatInfo, CoreType, EpcSection, L3MonitoringInfo, MbaInfo, MitigationCaps, PmuInfo,
+    PowerFeatures, RdtInfo, SevInfo, SgxInfo, XsaveComponent, XsaveInfo,
+};
+
+#[cfg(all(
+    feature = "linux_syscalls",
+    target_os = "linux",
+    any(target_arch = "x86", target_arch = "x86_64"),
+))]
+#[path = "os/linux.rs"]
+mod linux;
+
+#[cfg(all(
+    feature = "linux_syscalls",
+    target_os = "linux",
+    any(target_arch = "x86", target_arch = "x86_64"),
+))]
+pub use crate::linux::{detect_features_per_cpu, shstk_enabled, PerCpuFeatures};
+
 #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
 mod os {
     #[inline]
@@ -160,3 +289,9 @@ macro_rules! is_x86_feature_detected {
 }
 
 mod cache;
+
+mod feature_set;
+pub use crate::feature_set::{
+    check_compile_time_features, compile_time_features, ensure_compile_time_features,
+    missing_at_runtime, snapshot, FeatureSet, MissingFeatures,
+};
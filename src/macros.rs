@@ -1,3 +1,34 @@
+/// Defines a function which is a `const fn` if the `const_fn` feature is
+/// enabled (see `Cargo.toml` for why that's optional).
+macro_rules! maybe_const_fn {
+    ($(#[$attr:meta])* $vis:vis fn $($rest:tt)*) => {
+        #[cfg(feature = "const_fn")]
+        $(#[$attr])*
+        $vis const fn $($rest)*
+
+        #[cfg(not(feature = "const_fn"))]
+        $(#[$attr])*
+        $vis fn $($rest)*
+    };
+}
+
+maybe_const_fn! {
+    /// Compares two byte strings (`==` isn't usable in a `const fn`).
+    pub(crate) fn bytes_eq(a: &[u8], b: &[u8]) -> bool {
+        if a.len() != b.len() {
+            return false;
+        }
+        let mut i = 0;
+        while i < a.len() {
+            if a[i] != b[i] {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+}
+
 #[allow(unused)]
 macro_rules! features {
     (
@@ -17,7 +48,7 @@ macro_rules! features {
                 };
             )*
             $(
-                ($bind_feature) => { $macro_name!($feature_impl); };
+                ($bind_feature) => { $macro_name!($feature_impl) };
             )*
             $(
                 ($nort_feature) => {
@@ -30,7 +61,7 @@ macro_rules! features {
                 };
             )*
             ($t:tt,) => {
-                    $macro_name!($t);
+                $macro_name!($t)
             };
             ($t:tt) => {
                 compile_error!(
@@ -42,36 +73,71 @@ macro_rules! features {
             };
         }
 
-        /// Each variant denotes a position in a bitset for a particular feature.
+        /// A CPU feature which can be detected at runtime.
         ///
-        /// PLEASE: do not use this, it is an implementation detail subject
-        /// to change.
-        #[doc(hidden)]
+        /// The variants are named after the strings accepted by
+        /// `is_x86_feature_detected!` (with `.` replaced by `_`). More variants
+        /// may be added in the future, so don't match on this exhaustively.
         #[allow(non_camel_case_types)]
-        #[derive(Copy, Clone)]
+        // `#[non_exhaustive]` is too new for our MSRV.
+        #[allow(clippy::manual_non_exhaustive)]
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
         #[repr(u8)]
-        pub(crate) enum Feature {
+        pub enum Feature {
             $(
                 $(#[$feature_comment])*
                 $feature,
             )*
 
             // Do not add variants after last:
+            #[doc(hidden)]
             _last
         }
 
         impl Feature {
-            pub(crate) fn to_str(self) -> &'static str {
+            /// Every feature, in bit order.
+            pub(crate) const ALL: &'static [Feature] = &[$(Feature::$feature,)*];
+
+            /// The bits of the features enabled at compile time (with
+            /// `-C target-feature` or `-C target-cpu`).
+            #[allow(unknown_lints, unexpected_cfgs)]
+            pub(crate) const COMPILE_TIME_BITS: u128 = 0
+                $(| (cfg!(target_feature = $feature_lit) as u128) << (Feature::$feature as u32))*;
+
+            /// Returns the name of this feature, as accepted by
+            /// `is_x86_feature_detected!` (e.g. `"sse4.1"`).
+            pub fn name(self) -> &'static str {
                 match self {
                     $(Feature::$feature => $feature_lit,)*
-                    Feature::_last => unreachable!(),
+                    // Not a real feature, so it has no name (like the
+                    // placeholder variant on other architectures).
+                    Feature::_last => "",
                 }
             }
-            #[cfg(feature = "std_detect_env_override")]
-            pub(crate) fn from_str(s: &str) -> Result<Feature, ()> {
-                match s {
-                    $($feature_lit => Ok(Feature::$feature),)*
-                    _ => Err(())
+
+            maybe_const_fn! {
+                /// Returns the feature named `name` (e.g. `"sse4.1"`), or
+                /// `None` if it's unknown. This accepts the same names as
+                /// `is_x86_feature_detected!` (including aliases), except for
+                /// ones which can't be detected at runtime.
+                ///
+                /// With the `const_fn` feature enabled, this is a `const fn`,
+                /// which can be used to validate feature names at compile time.
+                /// That's the better option wherever it's available (see the
+                /// feature's entry in `Cargo.toml` for why it's optional).
+                pub fn from_name(name: &str) -> Option<Feature> {
+                    let name = name.as_bytes();
+                    $(
+                        if crate::macros::bytes_eq(name, $feature_lit.as_bytes()) {
+                            return Some(Feature::$feature);
+                        }
+                    )*
+                    $(
+                        if crate::macros::bytes_eq(name, $bind_feature.as_bytes()) {
+                            return Feature::from_name($feature_impl);
+                        }
+                    )*
+                    None
                 }
             }
         }
@@ -90,6 +156,8 @@ macro_rules! features {
                 /// subject to change.
                 #[inline]
                 #[doc(hidden)]
+                // Not every feature we detect is one `rustc` knows about.
+                #[allow(unknown_lints, unexpected_cfgs)]
                 pub fn $feature() -> bool {
                     cfg!(target_feature = $feature_lit) ||
                         $crate::check_for($crate::Feature::$feature)
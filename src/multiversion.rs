@@ -0,0 +1,190 @@
+//! Function multiversioning on top of `is_x86_feature_detected!`.
+
+/// Defines a function which dispatches to the best of several implementations,
+/// depending on which CPU features are detected at runtime.
+///
+/// This saves writing out the usual "check features, call the
+/// `#[target_feature]` function, otherwise fall back" dance by hand:
+///
+/// ```
+/// # #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+/// # mod imp {
+/// #[target_feature(enable = "avx2,fma")]
+/// unsafe fn sum_avx2(xs: &[f32]) -> f32 {
+///     xs.iter().sum()
+/// }
+///
+/// #[target_feature(enable = "sse4.1")]
+/// unsafe fn sum_sse41(xs: &[f32]) -> f32 {
+///     xs.iter().sum()
+/// }
+///
+/// fn sum_fallback(xs: &[f32]) -> f32 {
+///     xs.iter().sum()
+/// }
+///
+/// core_detect::multiversion! {
+///     /// Sums the values in `xs`.
+///     pub fn sum(xs: &[f32]) -> f32 {
+///         unsafe ["avx2", "fma"] => sum_avx2,
+///         unsafe ["sse4.1"] => sum_sse41,
+///         _ => sum_fallback,
+///     }
+/// }
+/// # pub fn main() {
+/// assert_eq!(sum(&[1.0, 2.0, 3.0]), 6.0);
+/// # }
+/// # }
+/// # #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+/// # fn main() { imp::main() }
+/// # #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+/// # fn main() {}
+/// ```
+///
+/// Candidates are tried in order, and the first one whose features are all
+/// detected is called, so they should be listed from best to worst. The
+/// fallback (`_`) is used if none of them apply, and must be a safe function.
+/// Feature names are checked by `is_x86_feature_detected!`, so a typo is a
+/// compile error. Detection uses the same cache, so after the first call, the
+/// cost of dispatching is a few bit tests.
+///
+/// The `unsafe` before each candidate is a promise that the candidate is safe
+/// to call whenever the listed features are available (that is, that it
+/// doesn't enable any features which aren't listed), since that can't be
+/// checked by the macro.
+///
+/// On architectures other than x86/x86_64, the candidates are ignored
+/// entirely (they needn't even exist), and the fallback is always called.
+///
+/// Limitations: the function can't be generic, and its parameters must be
+/// plain identifiers (not patterns).
+#[macro_export]
+macro_rules! multiversion {
+    (
+        $(#[$attr:meta])*
+        $vis:vis fn $name:ident($($arg:ident: $arg_ty:ty),* $(,)?) $(-> $ret:ty)? {
+            $(unsafe [$($feature:tt),+ $(,)?] => $candidate:path,)*
+            _ => $fallback:path $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis fn $name($($arg: $arg_ty),*) $(-> $ret)? {
+            $crate::multiversion!(
+                @dispatch ($($arg),*);
+                $(unsafe [$($feature),+] => $candidate,)*
+                _ => $fallback
+            )
+        }
+    };
+    (
+        @dispatch $args:tt;
+        $(unsafe [$($feature:tt),+] => $candidate:path,)*
+        _ => $fallback:path
+    ) => {{
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            $(
+                if true $(&& $crate::is_x86_feature_detected!($feature))+ {
+                    // Safety: The caller of `multiversion!` promised this is
+                    // safe if these features are available.
+                    return unsafe { $candidate $args };
+                }
+            )*
+        }
+        $fallback $args
+    }};
+}
+
+/// Like [`multiversion!`], but resolves which implementation to use once, on
+/// the first call, and calls it through a cached function pointer afterwards.
+///
+/// This is similar to an ELF `ifunc`: the function pointer initially points to
+/// a resolver, which performs feature detection (using the same cache as
+/// `is_x86_feature_detected!`), stores the chosen implementation in its place,
+/// and then calls it. After that, every call is a single load and an indirect
+/// call, with no bit tests at all. This is mostly useful for small functions
+/// that are called very often.
+///
+/// The syntax (and safety requirements) are the same as for
+/// [`multiversion!`]:
+///
+/// ```
+/// # #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+/// # mod imp {
+/// #[target_feature(enable = "popcnt")]
+/// unsafe fn count_ones_popcnt(xs: &[u64]) -> u32 {
+///     xs.iter().map(|x| x.count_ones()).sum()
+/// }
+///
+/// fn count_ones_fallback(xs: &[u64]) -> u32 {
+///     xs.iter().map(|x| x.count_ones()).sum()
+/// }
+///
+/// core_detect::ifunc! {
+///     /// Counts the set bits in `xs`.
+///     pub fn count_ones(xs: &[u64]) -> u32 {
+///         unsafe ["popcnt"] => count_ones_popcnt,
+///         _ => count_ones_fallback,
+///     }
+/// }
+/// # pub fn main() {
+/// assert_eq!(count_ones(&[1, 3, u64::max_value()]), 67);
+/// # }
+/// # }
+/// # #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+/// # fn main() { imp::main() }
+/// # #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+/// # fn main() {}
+/// ```
+///
+/// If several threads call the function before it has been resolved, each of
+/// them may run the resolver, but they'll all pick the same implementation.
+///
+/// Note that the indirect call prevents the implementation from being inlined
+/// into the caller, so for functions which are called in a loop, it's often
+/// better to move the loop into the implementations instead.
+#[macro_export]
+macro_rules! ifunc {
+    (
+        $(#[$attr:meta])*
+        $vis:vis fn $name:ident($($arg:ident: $arg_ty:ty),* $(,)?) $(-> $ret:ty)? {
+            $(unsafe [$($feature:tt),+ $(,)?] => $candidate:path,)*
+            _ => $fallback:path $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis fn $name($($arg: $arg_ty),*) $(-> $ret)? {
+            type Impl = unsafe fn($($arg_ty),*) $(-> $ret)?;
+
+            static IMPL: ::core::sync::atomic::AtomicPtr<()> =
+                ::core::sync::atomic::AtomicPtr::new(__core_detect_resolve as *mut ());
+
+            fn __core_detect_select() -> Impl {
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                {
+                    $(
+                        if true $(&& $crate::is_x86_feature_detected!($feature))+ {
+                            return $candidate;
+                        }
+                    )*
+                }
+                $fallback
+            }
+
+            fn __core_detect_resolve($($arg: $arg_ty),*) $(-> $ret)? {
+                let chosen = __core_detect_select();
+                // `Relaxed` is enough, since this only points at code.
+                IMPL.store(chosen as *mut (), ::core::sync::atomic::Ordering::Relaxed);
+                // Safety: The caller of `ifunc!` promised this is safe if the
+                // features we just detected are available.
+                unsafe { chosen($($arg),*) }
+            }
+
+            let ptr = IMPL.load(::core::sync::atomic::Ordering::Relaxed);
+            // Safety: `IMPL` only ever holds `__core_detect_resolve` or a
+            // function returned by `__core_detect_select`, both of which have
+            // the type `Impl`. Calling it is safe for the same reason as above.
+            unsafe { ::core::mem::transmute::<*mut (), Impl>(ptr)($($arg),*) }
+        }
+    };
+}
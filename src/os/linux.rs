@@ -0,0 +1,330 @@
+//! Linux-specific queries about what the kernel has enabled for us.
+//!
+//! These use raw system calls (rather than depending on `libc`), which needs
+//! inline assembly, so this module is only available with the
+//! `linux_syscalls` feature, and requires Rust 1.59 or newer.
+
+use core::fmt::{self, Write};
+use core::mem;
+
+use crate::FeatureSet;
+
+/// Thin wrappers around the `syscall` instruction.
+#[cfg(target_arch = "x86_64")]
+mod sys {
+    use core::arch::asm;
+
+    pub(crate) const SYS_WRITE: usize = 1;
+    pub(crate) const SYS_EXIT_GROUP: usize = 231;
+    pub(crate) const SYS_SCHED_SETAFFINITY: usize = 203;
+    pub(crate) const SYS_SCHED_GETAFFINITY: usize = 204;
+    pub(crate) const SYS_ARCH_PRCTL: usize = 158;
+
+    /// Performs the system call `nr` with up to three arguments, returning the
+    /// raw result (a negative errno on failure).
+    #[inline]
+    pub(crate) unsafe fn syscall3(nr: usize, a1: usize, a2: usize, a3: usize) -> isize {
+        let ret: isize;
+        asm!(
+            "syscall",
+            inlateout("rax") nr as isize => ret,
+            in("rdi") a1,
+            in("rsi") a2,
+            in("rdx") a3,
+            out("rcx") _,
+            out("r11") _,
+            options(nostack),
+        );
+        ret
+    }
+}
+
+/// Thin wrappers around `int 0x80`.
+#[cfg(target_arch = "x86")]
+mod sys {
+    use core::arch::asm;
+
+    pub(crate) const SYS_WRITE: usize = 4;
+    pub(crate) const SYS_EXIT_GROUP: usize = 252;
+    pub(crate) const SYS_SCHED_SETAFFINITY: usize = 241;
+    pub(crate) const SYS_SCHED_GETAFFINITY: usize = 242;
+
+    /// Performs the system call `nr` with up to three arguments, returning the
+    /// raw result (a negative errno on failure).
+    #[inline]
+    pub(crate) unsafe fn syscall3(nr: usize, a1: usize, a2: usize, a3: usize) -> isize {
+        let ret: isize;
+        asm!(
+            "int 0x80",
+            inlateout("eax") nr as isize => ret,
+            in("ebx") a1,
+            in("ecx") a2,
+            in("edx") a3,
+            options(nostack),
+        );
+        ret
+    }
+}
+
+const EINTR: isize = 4;
+
+/// Writes to stderr with `write(2, ...)`.
+struct Stderr;
+
+impl Write for Stderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut bytes = s.as_bytes();
+        while !bytes.is_empty() {
+            // Safety: The kernel only reads `bytes.len()` bytes from the
+            // pointer.
+            let ret =
+                unsafe { sys::syscall3(sys::SYS_WRITE, 2, bytes.as_ptr() as usize, bytes.len()) };
+            if ret == -EINTR {
+                continue;
+            }
+            if ret <= 0 {
+                return Err(fmt::Error);
+            }
+            bytes = &bytes[ret as usize..];
+        }
+        Ok(())
+    }
+}
+
+/// Writes `message` (and a newline) to stderr, and exits the process with
+/// status 1.
+///
+/// This doesn't unwind, or run destructors or `atexit` handlers, so it's fine
+/// to call before `main` (or from places that mustn't panic).
+pub(crate) fn exit_with_message(message: &dyn fmt::Display) -> ! {
+    // There's nothing useful we can do if this fails.
+    let _ = writeln!(Stderr, "{}", message);
+    loop {
+        // Safety: `exit_group` takes no pointers, and doesn't return.
+        unsafe {
+            sys::syscall3(sys::SYS_EXIT_GROUP, 1, 0, 0);
+        }
+    }
+}
+
+/// Calls [`ensure_compile_time_features`](crate::ensure_compile_time_features)
+/// when the program starts, before `main` runs.
+///
+/// This registers a constructor in the `.init_array` section, so invoke it (at
+/// item level) in your binary crate. If it's in a library, the linker may
+/// discard it unless something else in the same object file is used.
+///
+/// ```
+/// core_detect::ensure_compile_time_features_at_startup!();
+/// # fn main() {}
+/// ```
+///
+/// Only available on Linux with the `linux_syscalls` feature.
+#[macro_export]
+macro_rules! ensure_compile_time_features_at_startup {
+    () => {
+        const _: () = {
+            #[used]
+            #[link_section = ".init_array"]
+            static CHECK_FEATURES: extern "C" fn() = {
+                extern "C" fn check_features() {
+                    $crate::ensure_compile_time_features();
+                }
+                check_features
+            };
+        };
+    };
+}
+
+/// `arch_prctl` code for querying the enabled shadow stack features.
+const ARCH_SHSTK_STATUS: usize = 0x5005;
+/// Bit of the `ARCH_SHSTK_STATUS` result indicating shadow stacks are enabled.
+const ARCH_SHSTK_SHSTK: u64 = 1 << 0;
+
+/// Returns true if the kernel has enabled shadow stacks (CET-SS) for the
+/// current thread.
+///
+/// Unlike `is_x86_feature_detected!("shstk")`, which only tells you whether the
+/// CPU supports shadow stacks, this asks the kernel (via
+/// `arch_prctl(ARCH_SHSTK_STATUS)`) whether they are actually in use. This
+/// returns false on kernels which don't support shadow stacks, and on 32-bit
+/// x86, where Linux doesn't support them at all.
+///
+/// The result is not cached, as it's a per-thread property that can change
+/// over the lifetime of the thread.
+///
+/// Only available on Linux with the `linux_syscalls` feature.
+#[inline]
+pub fn shstk_enabled() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let mut features: u64 = 0;
+        // Safety: `ARCH_SHSTK_STATUS` writes a single `u64` to the pointer we
+        // pass, and fails with `EINVAL` on kernels that don't know about it.
+        let ret = unsafe {
+            sys::syscall3(
+                sys::SYS_ARCH_PRCTL,
+                ARCH_SHSTK_STATUS,
+                &mut features as *mut u64 as usize,
+                0,
+            )
+        };
+        ret == 0 && features & ARCH_SHSTK_SHSTK != 0
+    }
+    #[cfg(target_arch = "x86")]
+    {
+        false
+    }
+}
+
+/// The largest number of CPUs we can handle in an affinity mask. This matches
+/// the largest `CONFIG_NR_CPUS` the kernel allows.
+const MAX_CPUS: usize = 8192;
+const USIZE_BITS: usize = mem::size_of::<usize>() * 8;
+
+/// A CPU affinity mask, in the format `sched_{get,set}affinity` use.
+#[derive(Copy, Clone)]
+struct CpuSet([usize; MAX_CPUS / USIZE_BITS]);
+
+impl CpuSet {
+    fn empty() -> Self {
+        CpuSet([0; MAX_CPUS / USIZE_BITS])
+    }
+
+    fn single(cpu: usize) -> Self {
+        let mut set = CpuSet::empty();
+        set.0[cpu / USIZE_BITS] = 1 << (cpu % USIZE_BITS);
+        set
+    }
+
+    fn contains(&self, cpu: usize) -> bool {
+        self.0[cpu / USIZE_BITS] & (1 << (cpu % USIZE_BITS)) != 0
+    }
+
+    /// Returns the current thread's affinity mask.
+    fn current() -> Option<Self> {
+        let mut set = CpuSet::empty();
+        // Safety: The kernel writes at most `size_of_val(&set.0)` bytes to the
+        // pointer we pass (and fails if the mask doesn't fit).
+        let ret = unsafe {
+            sys::syscall3(
+                sys::SYS_SCHED_GETAFFINITY,
+                0,
+                mem::size_of_val(&set.0),
+                set.0.as_mut_ptr() as usize,
+            )
+        };
+        if ret < 0 {
+            None
+        } else {
+            Some(set)
+        }
+    }
+
+    /// Sets the current thread's affinity mask, returning false on failure
+    /// (for example, if the mask only contains CPUs which are offline).
+    fn make_current(&self) -> bool {
+        // Safety: The kernel only reads `size_of_val(&self.0)` bytes from the
+        // pointer we pass.
+        let ret = unsafe {
+            sys::syscall3(
+                sys::SYS_SCHED_SETAFFINITY,
+                0,
+                mem::size_of_val(&self.0),
+                self.0.as_ptr() as usize,
+            )
+        };
+        ret == 0
+    }
+}
+
+/// Restores the thread's original affinity mask when dropped, even if we
+/// unwind.
+struct RestoreAffinity(CpuSet);
+
+impl Drop for RestoreAffinity {
+    fn drop(&mut self) {
+        self.0.make_current();
+    }
+}
+
+/// The result of [`detect_features_per_cpu`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PerCpuFeatures {
+    common: FeatureSet,
+    any: FeatureSet,
+    cpus: usize,
+}
+
+impl PerCpuFeatures {
+    /// The features detected on every CPU that was checked.
+    ///
+    /// These are the only features that are safe to use on a thread which may
+    /// run on any of them.
+    #[inline]
+    pub fn common(&self) -> FeatureSet {
+        self.common
+    }
+
+    /// The features detected on some, but not all, of the CPUs that were
+    /// checked. Normally, this is empty.
+    #[inline]
+    pub fn differing(&self) -> FeatureSet {
+        self.any.difference(&self.common)
+    }
+
+    /// The number of CPUs that were checked.
+    #[inline]
+    pub fn cpu_count(&self) -> usize {
+        self.cpus
+    }
+}
+
+/// Runs feature detection on each CPU the current thread is allowed to run on,
+/// and reports the features they have in common, as well as any that differ.
+///
+/// `is_x86_feature_detected!` only checks the CPU it happens to run on first,
+/// and assumes the rest are identical. That's normally true, but not always
+/// (for example, on some heterogeneous systems or misconfigured VMs), so this
+/// can be used at startup to avoid enabling features that aren't available
+/// everywhere. The cache used by `is_x86_feature_detected!` is not affected.
+///
+/// This works by temporarily pinning the current thread to each CPU in its
+/// affinity mask in turn. `on_cpu` is called with the index and features of
+/// each CPU as it is checked (while the thread is still pinned to it). The
+/// original affinity mask is restored before returning, even if `on_cpu`
+/// panics. CPUs which go offline during the check are skipped.
+///
+/// Returns `None` if the affinity mask couldn't be queried (for example, on a
+/// system with more than 8192 CPUs), or if no CPU could be checked.
+///
+/// Only available on Linux with the `linux_syscalls` feature.
+pub fn detect_features_per_cpu<F>(mut on_cpu: F) -> Option<PerCpuFeatures>
+where
+    F: FnMut(usize, FeatureSet),
+{
+    let original = CpuSet::current()?;
+    let _restore = RestoreAffinity(original);
+
+    let mut result: Option<PerCpuFeatures> = None;
+    for cpu in (0..MAX_CPUS).filter(|&cpu| original.contains(cpu)) {
+        if !CpuSet::single(cpu).make_current() {
+            continue;
+        }
+        let features = FeatureSet::from_initializer(crate::os::detect_features());
+        on_cpu(cpu, features);
+        result = Some(match result {
+            None => PerCpuFeatures {
+                common: features,
+                any: features,
+                cpus: 1,
+            },
+            Some(r) => PerCpuFeatures {
+                common: r.common.intersection(&features),
+                any: r.any.union(&features),
+                cpus: r.cpus + 1,
+            },
+        });
+    }
+    result
+}
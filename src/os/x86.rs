@@ -1,14 +1,42 @@
 //! x86 run-time feature detection is OS independent.
 
+// `__cpuid` and friends are safe to call on newer compilers, but we still
+// support ones where they're `unsafe`.
+#![allow(unused_unsafe)]
+
 #[cfg(target_arch = "x86")]
 use core::arch::x86::*;
 #[cfg(target_arch = "x86_64")]
 use core::arch::x86_64::*;
 
-use core::mem;
-
 use crate::{cache, Feature};
 
+#[path = "x86/hybrid.rs"]
+mod hybrid;
+#[path = "x86/intel_tee.rs"]
+mod intel_tee;
+#[path = "x86/mitigations.rs"]
+mod mitigations;
+#[path = "x86/pmu.rs"]
+mod pmu;
+#[path = "x86/power.rs"]
+mod power;
+#[path = "x86/rdt.rs"]
+mod rdt;
+#[path = "x86/sev.rs"]
+mod sev;
+#[path = "x86/xsave.rs"]
+mod xsave;
+
+pub use self::hybrid::{core_type, core_type_and_model_id, CoreType};
+pub use self::intel_tee::{is_tdx_guest, sgx_info, EpcSection, SgxInfo};
+pub use self::mitigations::{mitigation_caps, MitigationCaps};
+pub use self::pmu::{pmu_info, PmuInfo};
+pub use self::power::{power_features, PowerFeatures};
+pub use self::rdt::{rdt_info, AmdMbaInfo, CatInfo, L3MonitoringInfo, MbaInfo, RdtInfo};
+pub use self::sev::{sev_info, SevInfo};
+pub use self::xsave::{xsave_info, XsaveComponent, XsaveInfo};
+
 mod bit {
     #[inline]
     pub(crate) fn test(x: usize, bit: u32) -> bool {
@@ -54,6 +82,98 @@ fn have_cpuid() -> bool {
     }
 }
 
+/// Extracts the vendor ID string from the result of CPUID leaf 0. The 12 ASCII
+/// chars are returned in EBX, EDX, and ECX (in that order).
+fn vendor_id(leaf0: &CpuidResult) -> [u8; 12] {
+    let mut vendor_id = [0u8; 12];
+    vendor_id[0..4].copy_from_slice(&leaf0.ebx.to_ne_bytes());
+    vendor_id[4..8].copy_from_slice(&leaf0.edx.to_ne_bytes());
+    vendor_id[8..12].copy_from_slice(&leaf0.ecx.to_ne_bytes());
+    vendor_id
+}
+
+/// Returns true for AMD CPUs, and Hygon CPUs, which share AMD's definitions of
+/// the vendor-specific CPUID bits (see the comment about Hygon in
+/// `detect_features`).
+fn is_amd_like_vendor(vendor_id: &[u8; 12]) -> bool {
+    *vendor_id == *b"AuthenticAMD" || *vendor_id == *b"HygonGenuine"
+}
+
+/// Like `is_amd_like_vendor`, but for the CPU we're running on.
+pub(crate) fn is_amd_like() -> bool {
+    match cpuid(0, 0) {
+        Some(leaf0) => is_amd_like_vendor(&vendor_id(&leaf0)),
+        None => false,
+    }
+}
+
+/// Queries `cpuid` for `leaf`/`sub_leaf`, returning `None` if `cpuid` is not
+/// available or the CPU does not support `leaf`.
+///
+/// This is for the (uncached) queries that read more than a few feature bits,
+/// and handles both the basic and extended leaf ranges.
+pub(crate) fn cpuid(leaf: u32, sub_leaf: u32) -> Option<CpuidResult> {
+    if !have_cpuid() {
+        return None;
+    }
+    // The max basic leaf is returned in EAX for leaf 0, and the max extended
+    // leaf in EAX for leaf 0x8000_0000. CPUs without any extended leaves are
+    // allowed to return garbage for the latter, hence the extra check.
+    let range_start = leaf & 0x8000_0000;
+    let max_leaf = unsafe { __cpuid(range_start) }.eax;
+    if max_leaf < range_start || leaf > max_leaf {
+        return None;
+    }
+    Some(unsafe { __cpuid_count(leaf, sub_leaf) })
+}
+
+/// Returns the size (in bytes) of the cache line flushed by `clflush`, or
+/// `None` if the CPU doesn't support `clflush`.
+///
+/// This comes from CPUID leaf 1, and is what `clflush` and `clflushopt` use,
+/// which may differ from the size of the cache lines in the actual caches.
+pub fn clflush_line_size() -> Option<usize> {
+    let CpuidResult { ebx, edx, .. } = cpuid(0x0000_0001_u32, 0)?;
+    if !bit::test(edx as usize, 19) {
+        return None;
+    }
+    // EBX[15:8] is the line size in 8-byte units.
+    Some(((ebx >> 8) & 0xff) as usize * 8)
+}
+
+/// Returns the widths (in bits) of physical addresses, linear (virtual)
+/// addresses, and guest physical addresses, in that order.
+///
+/// These come from CPUID leaf 0x8000_0008. On CPUs which don't support that
+/// leaf, physical addresses are assumed to be 36 bits wide if PAE or PSE-36 is
+/// supported (and 32 bits otherwise), and linear addresses 32 bits wide. The
+/// guest physical address width is the same as the physical one, unless the
+/// CPU reports otherwise.
+///
+/// Whether 5-level paging (57-bit linear addresses) is supported is reported by
+/// `is_x86_feature_detected!("la57")`.
+pub fn address_sizes() -> (u8, u8, u8) {
+    if let Some(CpuidResult { eax, .. }) = cpuid(0x8000_0008_u32, 0) {
+        let phys_bits = eax as u8;
+        let virt_bits = (eax >> 8) as u8;
+        let guest_phys_bits = match (eax >> 16) as u8 {
+            0 => phys_bits,
+            n => n,
+        };
+        return (phys_bits, virt_bits, guest_phys_bits);
+    }
+    // PAE is EDX[6], PSE-36 is EDX[17].
+    let phys_bits = match cpuid(0x0000_0001_u32, 0) {
+        Some(CpuidResult { edx, .. })
+            if bit::test(edx as usize, 6) || bit::test(edx as usize, 17) =>
+        {
+            36
+        }
+        _ => 32,
+    };
+    (phys_bits, 32, phys_bits)
+}
+
 /// Run-time feature detection on x86 works by using the CPUID instruction.
 ///
 /// The [CPUID Wikipedia page][wiki_cpuid] contains
@@ -87,19 +207,8 @@ pub(crate) fn detect_features() -> cache::Initializer {
     // 0x8000_0000]. - The vendor ID is stored in 12 u8 ascii chars,
     // returned in EBX, EDX, and   ECX (in that order):
     let (max_basic_leaf, vendor_id) = unsafe {
-        let CpuidResult {
-            eax: max_basic_leaf,
-            ebx,
-            ecx,
-            edx,
-        } = __cpuid(0);
-        let vendor_id: [[u8; 4]; 3] = [
-            mem::transmute(ebx),
-            mem::transmute(edx),
-            mem::transmute(ecx),
-        ];
-        let vendor_id: [u8; 12] = mem::transmute(vendor_id);
-        (max_basic_leaf, vendor_id)
+        let leaf0 = __cpuid(0);
+        (leaf0.eax, vendor_id(&leaf0))
     };
 
     if max_basic_leaf < 1 {
@@ -117,11 +226,24 @@ pub(crate) fn detect_features() -> cache::Initializer {
 
     // EAX = 7, ECX = 0: Queries "Extended Features";
     // Contains information about bmi,bmi2, and avx2 support.
-    let (extended_features_ebx, extended_features_ecx) = if max_basic_leaf >= 7 {
-        let CpuidResult { ebx, ecx, .. } = unsafe { __cpuid(0x0000_0007_u32) };
-        (ebx, ecx)
+    let (
+        extended_features_ebx,
+        extended_features_ecx,
+        extended_features_edx,
+        extended_features_eax_leaf_1,
+    ) = if max_basic_leaf >= 7 {
+        let CpuidResult { eax, ebx, ecx, edx } = unsafe { __cpuid(0x0000_0007_u32) };
+        // EAX = 7, ECX = 1: Only valid if the max subleaf (returned in EAX
+        // for subleaf 0) is at least 1.
+        let eax_leaf_1 = if eax >= 1 {
+            let CpuidResult { eax, .. } = unsafe { __cpuid_count(0x0000_0007_u32, 1) };
+            eax
+        } else {
+            0
+        };
+        (ebx, ecx, edx, eax_leaf_1)
     } else {
-        (0, 0) // CPUID does not support "Extended Features"
+        (0, 0, 0, 0) // CPUID does not support "Extended Features"
     };
 
     // EAX = 0x8000_0000, ECX = 0: Get Highest Extended Function Supported
@@ -134,9 +256,28 @@ pub(crate) fn detect_features() -> cache::Initializer {
 
     // EAX = 0x8000_0001, ECX=0: Queries "Extended Processor Info and Feature
     // Bits"
-    let extended_proc_info_ecx = if extended_max_basic_leaf >= 1 {
-        let CpuidResult { ecx, .. } = unsafe { __cpuid(0x8000_0001_u32) };
-        ecx
+    let (extended_proc_info_ecx, extended_proc_info_edx) = if extended_max_basic_leaf >= 1 {
+        let CpuidResult { ecx, edx, .. } = unsafe { __cpuid(0x8000_0001_u32) };
+        (ecx, edx)
+    } else {
+        (0, 0)
+    };
+
+    // EAX = 0x14, ECX = 0: Queries "Intel Processor Trace Enumeration";
+    // Only valid if leaf 7 reports Intel PT support (EBX[25]).
+    let processor_trace_ebx =
+        if max_basic_leaf >= 0x14 && bit::test(extended_features_ebx as usize, 25) {
+            let CpuidResult { ebx, .. } = unsafe { __cpuid_count(0x0000_0014_u32, 0) };
+            ebx
+        } else {
+            0
+        };
+
+    // EAX = 0x8000_0008, ECX = 0: Queries "Virtual and Physical Address
+    // Sizes", which also contains some extended feature bits in EBX.
+    let extended_address_sizes_ebx = if extended_max_basic_leaf >= 0x8000_0008 {
+        let CpuidResult { ebx, .. } = unsafe { __cpuid(0x8000_0008_u32) };
+        ebx
     } else {
         0
     };
@@ -161,7 +302,6 @@ pub(crate) fn detect_features() -> cache::Initializer {
         enable(proc_info_ecx, 30, Feature::rdrand);
         enable(extended_features_ebx, 18, Feature::rdseed);
         enable(extended_features_ebx, 19, Feature::adx);
-        enable(extended_features_ebx, 11, Feature::rtm);
         enable(proc_info_edx, 4, Feature::tsc);
         enable(proc_info_edx, 23, Feature::mmx);
         enable(proc_info_edx, 24, Feature::fxsr);
@@ -169,9 +309,61 @@ pub(crate) fn detect_features() -> cache::Initializer {
         enable(proc_info_edx, 26, Feature::sse2);
         enable(extended_features_ebx, 29, Feature::sha);
 
+        // TSX: When TSX has been disabled through `IA32_TSX_CTRL` (e.g. with
+        // `tsx=off`), the CPU clears the HLE and RTM bits itself. Microcode
+        // updates can instead leave RTM advertised but set RTM_ALWAYS_ABORT
+        // (EDX[11]), in which case every `xbegin` aborts, so we don't report
+        // RTM. TSXLDTRK is only useful inside a transaction, and CPUs can keep
+        // advertising it after TSX is disabled, so we only report it with RTM.
+        //
+        // Note that `TSX_FORCE_ABORT` (EDX[13]) only tells us that the MSR of
+        // the same name exists, we can't read its value from user mode.
+        enable(extended_features_ebx, 4, Feature::hle);
+        if bit::test(extended_features_ebx as usize, 11)
+            && !bit::test(extended_features_edx as usize, 11)
+        {
+            enable(extended_features_ebx, 11, Feature::rtm);
+            enable(extended_features_edx, 16, Feature::tsxldtrk);
+        }
+
+        // CET (Control-flow Enforcement Technology). These only say whether the
+        // CPU supports them, see `linux::shstk_enabled` for whether the OS enabled
+        // shadow stacks for us.
+        enable(extended_features_ecx, 7, Feature::shstk);
+        enable(extended_features_edx, 20, Feature::ibt);
+
+        // Protection keys: `rdpkru` and `wrpkru` fault unless the OS has set
+        // `CR4.PKE`, which is reflected in OSPKE (ECX[4]). So like `xsave`, we
+        // only report `pku` if the OS has enabled it too.
+        if bit::test(extended_features_ecx as usize, 4) {
+            enable(extended_features_ecx, 3, Feature::pku);
+            enable(extended_features_ecx, 4, Feature::ospke);
+        }
+
+        enable(extended_features_ecx, 16, Feature::la57);
+
         enable(extended_features_ebx, 3, Feature::bmi1);
         enable(extended_features_ebx, 8, Feature::bmi2);
 
+        // Timing and wait instructions:
+        enable(extended_proc_info_edx, 27, Feature::rdtscp);
+        enable(extended_features_ecx, 22, Feature::rdpid);
+        enable(extended_features_ecx, 5, Feature::waitpkg);
+        enable(extended_features_edx, 14, Feature::serialize);
+        enable(extended_features_edx, 5, Feature::uintr);
+        enable(extended_features_eax_leaf_1, 22, Feature::hreset);
+
+        // Cache management and direct store instructions:
+        enable(proc_info_edx, 19, Feature::clflush);
+        enable(extended_features_ebx, 23, Feature::clflushopt);
+        enable(extended_features_ebx, 24, Feature::clwb);
+        enable(extended_features_ecx, 25, Feature::cldemote);
+        enable(extended_features_ecx, 27, Feature::movdiri);
+        enable(extended_features_ecx, 28, Feature::movdir64b);
+        enable(extended_features_ecx, 29, Feature::enqcmd);
+        enable(processor_trace_ebx, 4, Feature::ptwrite);
+        enable(extended_address_sizes_ebx, 9, Feature::wbnoinvd);
+
         // `XSAVE` and `AVX` support:
         let cpu_xsave = bit::test(proc_info_ecx as usize, 26);
         if cpu_xsave {
@@ -253,10 +445,10 @@ pub(crate) fn detect_features() -> cache::Initializer {
                         enable(extended_features_ebx, 30, Feature::avx512bw);
                         enable(extended_features_ebx, 31, Feature::avx512vl);
                         enable(extended_features_ecx, 1, Feature::avx512vbmi);
-                        enable(extended_features_ecx, 5, Feature::avx512bf16);
+                        enable(extended_features_eax_leaf_1, 5, Feature::avx512bf16);
                         enable(extended_features_ecx, 6, Feature::avx512vbmi2);
                         enable(extended_features_ecx, 8, Feature::avx512gfni);
-                        enable(extended_features_ecx, 8, Feature::avx512vp2intersect);
+                        enable(extended_features_edx, 8, Feature::avx512vp2intersect);
                         enable(extended_features_ecx, 9, Feature::avx512vaes);
                         enable(extended_features_ecx, 10, Feature::avx512vpclmulqdq);
                         enable(extended_features_ecx, 11, Feature::avx512vnni);
@@ -286,7 +478,7 @@ pub(crate) fn detect_features() -> cache::Initializer {
         // Related AMD CPUID specification is https://www.amd.com/system/files/TechDocs/25481.pdf.
         // Related Hygon kernel patch can be found on
         // http://lkml.kernel.org/r/5ce86123a7b9dad925ac583d88d2f921040e859b.1538583282.git.puwen@hygon.cn
-        if vendor_id == *b"AuthenticAMD" || vendor_id == *b"HygonGenuine" {
+        if is_amd_like_vendor(&vendor_id) {
             // These features are available on AMD arch CPUs:
             enable(extended_proc_info_ecx, 6, Feature::sse4a);
             enable(extended_proc_info_ecx, 21, Feature::tbm);
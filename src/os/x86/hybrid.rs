@@ -0,0 +1,52 @@
+//! Reports which kind of core the current thread is running on, for hybrid
+//! CPUs (Intel Alder Lake and later).
+
+use super::{bit, cpuid};
+
+/// The kind of core on a hybrid CPU, as returned by [`core_type`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CoreType {
+    /// An efficiency core ("E-core", an Intel Atom core).
+    Efficiency,
+    /// A performance core ("P-core", an Intel Core core).
+    Performance,
+    /// A core type not known to this crate, holding the raw value from CPUID
+    /// leaf 0x1A `EAX[31:24]`.
+    Other(u8),
+}
+
+/// Returns the type of the logical CPU the current thread is running on, or
+/// `None` if the CPU isn't a hybrid CPU.
+///
+/// Unlike `is_x86_feature_detected!`, this is not cached, since it depends on
+/// which core the thread is currently scheduled on. Note that the OS is free
+/// to migrate the thread at any point, so unless its affinity has been
+/// restricted, the result may be out of date by the time it's returned.
+#[inline]
+pub fn core_type() -> Option<CoreType> {
+    core_type_and_model_id().map(|(ty, _)| ty)
+}
+
+/// Like [`core_type`], but also returns the native model ID of the core (CPUID
+/// leaf 0x1A `EAX[23:0]`), which identifies its microarchitecture.
+///
+/// Both values come from a single `cpuid` query, so they always describe the
+/// same core.
+pub fn core_type_and_model_id() -> Option<(CoreType, u32)> {
+    // The hybrid flag is leaf 7 EDX[15].
+    let leaf7 = cpuid(0x0000_0007_u32, 0)?;
+    if !bit::test(leaf7.edx as usize, 15) {
+        return None;
+    }
+    let eax = cpuid(0x0000_001a_u32, 0)?.eax;
+    if eax == 0 {
+        // Leaf 0x1A isn't enumerated for this core.
+        return None;
+    }
+    let ty = match (eax >> 24) as u8 {
+        0x20 => CoreType::Efficiency,
+        0x40 => CoreType::Performance,
+        other => CoreType::Other(other),
+    };
+    Some((ty, eax & 0x00ff_ffff))
+}
@@ -0,0 +1,134 @@
+//! Reports support for Intel's trusted execution technologies: SGX (Software
+//! Guard Extensions) and TDX (Trust Domain Extensions).
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::CpuidResult;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::CpuidResult;
+
+use super::{bit, cpuid, vendor_id};
+
+/// Information about the CPU's SGX support, as returned by [`sgx_info`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SgxInfo {
+    /// CPUID leaf 7, ECX.
+    leaf7_ecx: u32,
+    /// CPUID leaf 0x12, sub-leaf 0, EAX and EDX.
+    leaf12: [u32; 2],
+}
+
+/// A section of the Enclave Page Cache, as returned by
+/// [`SgxInfo::epc_section`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EpcSection {
+    base: u64,
+    size: u64,
+}
+
+/// Returns information about SGX, or `None` if the CPU doesn't support it.
+///
+/// Note that SGX may be supported but disabled (for example, in the firmware
+/// settings), in which case this returns `Some`, but neither
+/// [`SgxInfo::sgx1`] nor [`SgxInfo::sgx2`] is true. Whether the OS lets you
+/// create enclaves is a separate question.
+///
+/// This reads leaf 7, and leaf 0x12 sub-leaf 0 if SGX is supported.
+pub fn sgx_info() -> Option<SgxInfo> {
+    // SGX is leaf 7 EBX[2].
+    let leaf7 = cpuid(0x0000_0007_u32, 0)?;
+    if !bit::test(leaf7.ebx as usize, 2) {
+        return None;
+    }
+    let leaf12 = cpuid(0x0000_0012_u32, 0).map_or([0, 0], |r| [r.eax, r.edx]);
+    Some(SgxInfo {
+        leaf7_ecx: leaf7.ecx,
+        leaf12,
+    })
+}
+
+impl SgxInfo {
+    /// The SGX1 leaf functions are supported (leaf 0x12 `EAX[0]`).
+    #[inline]
+    pub fn sgx1(&self) -> bool {
+        bit::test(self.leaf12[0] as usize, 0)
+    }
+
+    /// The SGX2 leaf functions (dynamic enclave memory management) are
+    /// supported (leaf 0x12 `EAX[1]`).
+    #[inline]
+    pub fn sgx2(&self) -> bool {
+        bit::test(self.leaf12[0] as usize, 1)
+    }
+
+    /// Flexible launch control is supported, so the OS can choose which
+    /// launch enclaves to trust (SGX_LC, leaf 7 `ECX[30]`).
+    #[inline]
+    pub fn flc(&self) -> bool {
+        bit::test(self.leaf7_ecx as usize, 30)
+    }
+
+    /// The log2 of the maximum enclave size outside of 64-bit mode (leaf 0x12
+    /// `EDX[7:0]`).
+    #[inline]
+    pub fn max_enclave_size_log2_32(&self) -> u8 {
+        self.leaf12[1] as u8
+    }
+
+    /// The log2 of the maximum enclave size in 64-bit mode (leaf 0x12
+    /// `EDX[15:8]`).
+    #[inline]
+    pub fn max_enclave_size_log2_64(&self) -> u8 {
+        (self.leaf12[1] >> 8) as u8
+    }
+
+    /// Returns the `index`th Enclave Page Cache section, or `None` if there is
+    /// no such section. Sections are numbered from 0, and there are no gaps,
+    /// so the layout can be enumerated by counting up until this returns
+    /// `None`.
+    ///
+    /// Each section is read from leaf 0x12, sub-leaf `index + 2`, when this
+    /// is called.
+    pub fn epc_section(&self, index: u32) -> Option<EpcSection> {
+        // The sections are described by sub-leaves 2 and up.
+        let CpuidResult { eax, ebx, ecx, edx } = cpuid(0x0000_0012_u32, index.checked_add(2)?)?;
+        // EAX[3:0] is the sub-leaf type: 0 means invalid (no more sections),
+        // and 1 means an EPC section.
+        if eax & 0xf != 1 {
+            return None;
+        }
+        // The address and size are split into bits [31:12] (EAX and ECX) and
+        // [51:32] (EBX and EDX).
+        let join = |lo: u32, hi: u32| u64::from(lo & 0xffff_f000) | u64::from(hi & 0xf_ffff) << 32;
+        Some(EpcSection {
+            base: join(eax, ebx),
+            size: join(ecx, edx),
+        })
+    }
+}
+
+impl EpcSection {
+    /// The physical address of the start of the section.
+    #[inline]
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// The size of the section, in bytes.
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Returns true if we're running as an Intel TDX guest (a "trust domain").
+///
+/// This checks for the `"IntelTDX    "` signature in CPUID leaf 0x21, which is
+/// only present inside a trust domain.
+///
+/// The leaf is read again on each call.
+pub fn is_tdx_guest() -> bool {
+    match cpuid(0x0000_0021_u32, 0) {
+        Some(leaf) => vendor_id(&leaf) == *b"IntelTDX    ",
+        None => false,
+    }
+}
@@ -0,0 +1,138 @@
+//! Reports hardware support for speculative-execution mitigations.
+//!
+//! These aren't instructions (and have no `target_feature` names), so they're
+//! kept out of the `is_x86_feature_detected!` table.
+
+use super::{bit, cpuid};
+
+/// The speculative-execution mitigations the CPU advertises, as returned by
+/// [`mitigation_caps`].
+///
+/// Most of these indicate that an MSR (such as `IA32_SPEC_CTRL`) or a control
+/// bit within one exists. Whether the OS actually uses them is a separate
+/// question, which generally can't be answered from user mode.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MitigationCaps {
+    /// CPUID leaf 7, sub-leaf 0, EDX.
+    leaf7_edx: u32,
+    /// CPUID leaf 7, sub-leaf 2, EDX.
+    leaf7_2_edx: u32,
+    /// CPUID leaf 0x8000_0008, EBX.
+    ext8_ebx: u32,
+}
+
+/// Returns the speculative-execution mitigations supported by the CPU.
+///
+/// This reads leaf 7 (sub-leaves 0 and 2) and leaf 0x8000_0008. If `cpuid`
+/// isn't available, nothing is reported as supported.
+pub fn mitigation_caps() -> MitigationCaps {
+    let mut caps = MitigationCaps::default();
+    if let Some(leaf7) = cpuid(0x0000_0007_u32, 0) {
+        caps.leaf7_edx = leaf7.edx;
+        // EAX is the max sub-leaf.
+        if leaf7.eax >= 2 {
+            if let Some(leaf7_2) = cpuid(0x0000_0007_u32, 2) {
+                caps.leaf7_2_edx = leaf7_2.edx;
+            }
+        }
+    }
+    if let Some(ext8) = cpuid(0x8000_0008_u32, 0) {
+        caps.ext8_ebx = ext8.ebx;
+    }
+    caps
+}
+
+impl MitigationCaps {
+    /// Intel: `IA32_SPEC_CTRL.IBRS` and `IA32_PRED_CMD.IBPB` are supported
+    /// (leaf 7 `EDX[26]`).
+    #[inline]
+    pub fn ibrs_ibpb(&self) -> bool {
+        bit::test(self.leaf7_edx as usize, 26)
+    }
+
+    /// Intel: `IA32_SPEC_CTRL.STIBP` is supported (leaf 7 `EDX[27]`).
+    #[inline]
+    pub fn stibp(&self) -> bool {
+        bit::test(self.leaf7_edx as usize, 27)
+    }
+
+    /// Intel: `IA32_FLUSH_CMD.L1D_FLUSH` is supported (leaf 7 `EDX[28]`).
+    #[inline]
+    pub fn l1d_flush(&self) -> bool {
+        bit::test(self.leaf7_edx as usize, 28)
+    }
+
+    /// Intel: `verw` clears CPU buffers (leaf 7 `EDX[10]`).
+    #[inline]
+    pub fn md_clear(&self) -> bool {
+        bit::test(self.leaf7_edx as usize, 10)
+    }
+
+    /// Intel: The `IA32_ARCH_CAPABILITIES` MSR is supported (leaf 7 `EDX[29]`).
+    #[inline]
+    pub fn arch_capabilities(&self) -> bool {
+        bit::test(self.leaf7_edx as usize, 29)
+    }
+
+    /// Intel: `IA32_SPEC_CTRL.SSBD` is supported (leaf 7 `EDX[31]`).
+    #[inline]
+    pub fn ssbd(&self) -> bool {
+        bit::test(self.leaf7_edx as usize, 31)
+    }
+
+    /// Intel: `IA32_SPEC_CTRL.PSFD` is supported (leaf 7.2 `EDX[0]`).
+    #[inline]
+    pub fn psfd(&self) -> bool {
+        bit::test(self.leaf7_2_edx as usize, 0)
+    }
+
+    /// Intel: `IA32_SPEC_CTRL.BHI_DIS_S` is supported (leaf 7.2 `EDX[4]`).
+    #[inline]
+    pub fn bhi_ctrl(&self) -> bool {
+        bit::test(self.leaf7_2_edx as usize, 4)
+    }
+
+    /// AMD: `IA32_PRED_CMD.IBPB` is supported (0x8000_0008 `EBX[12]`).
+    #[inline]
+    pub fn amd_ibpb(&self) -> bool {
+        bit::test(self.ext8_ebx as usize, 12)
+    }
+
+    /// AMD: `IA32_SPEC_CTRL.IBRS` is supported (0x8000_0008 `EBX[14]`).
+    #[inline]
+    pub fn amd_ibrs(&self) -> bool {
+        bit::test(self.ext8_ebx as usize, 14)
+    }
+
+    /// AMD: `IA32_SPEC_CTRL.STIBP` is supported (0x8000_0008 `EBX[15]`).
+    #[inline]
+    pub fn amd_stibp(&self) -> bool {
+        bit::test(self.ext8_ebx as usize, 15)
+    }
+
+    /// AMD: `IA32_SPEC_CTRL.SSBD` is supported (0x8000_0008 `EBX[24]`).
+    #[inline]
+    pub fn amd_ssbd(&self) -> bool {
+        bit::test(self.ext8_ebx as usize, 24)
+    }
+
+    /// AMD: `VIRT_SPEC_CTRL.SSBD` is supported (0x8000_0008 `EBX[25]`). This is
+    /// what hypervisors expose to guests instead of `amd_ssbd`.
+    #[inline]
+    pub fn amd_virt_ssbd(&self) -> bool {
+        bit::test(self.ext8_ebx as usize, 25)
+    }
+
+    /// AMD: The CPU isn't vulnerable to Speculative Store Bypass
+    /// (0x8000_0008 `EBX[26]`).
+    #[inline]
+    pub fn amd_ssb_no(&self) -> bool {
+        bit::test(self.ext8_ebx as usize, 26)
+    }
+
+    /// AMD: `IA32_SPEC_CTRL.PSFD` is supported (0x8000_0008 `EBX[28]`).
+    #[inline]
+    pub fn amd_psfd(&self) -> bool {
+        bit::test(self.ext8_ebx as usize, 28)
+    }
+}
@@ -0,0 +1,171 @@
+//! Reports the capabilities of the performance monitoring unit (PMU).
+
+use super::{bit, cpuid, is_amd_like};
+
+/// The performance counters the CPU provides, as returned by [`pmu_info`].
+///
+/// Intel's architectural performance monitoring is described by CPUID leaf
+/// 0xA, and AMD's counter extensions by leaf 0x8000_0001 ECX and leaf
+/// 0x8000_0022. Hypervisors often hide the PMU from guests, in which case
+/// everything here reads as zero or false.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PmuInfo {
+    /// CPUID leaf 0xA, EAX..EDX.
+    leaf_a: [u32; 4],
+    /// CPUID leaf 0x8000_0001, ECX (only on AMD and Hygon).
+    ext1_ecx: u32,
+    /// CPUID leaf 0x8000_0022, EAX and EBX (only on AMD and Hygon).
+    ext22: [u32; 2],
+    /// Whether this is an AMD (or Hygon) CPU.
+    amd: bool,
+}
+
+/// Returns information about the performance monitoring unit.
+///
+/// This reads leaf 0 (for the vendor) and leaf 0xA, and on AMD, leaves
+/// 0x8000_0001 and 0x8000_0022.
+pub fn pmu_info() -> PmuInfo {
+    let mut info = PmuInfo::default();
+    if let Some(leaf) = cpuid(0x0000_000a_u32, 0) {
+        info.leaf_a = [leaf.eax, leaf.ebx, leaf.ecx, leaf.edx];
+    }
+    if is_amd_like() {
+        info.amd = true;
+        if let Some(leaf) = cpuid(0x8000_0001_u32, 0) {
+            info.ext1_ecx = leaf.ecx;
+        }
+        if let Some(leaf) = cpuid(0x8000_0022_u32, 0) {
+            info.ext22 = [leaf.eax, leaf.ebx];
+        }
+    }
+    info
+}
+
+impl PmuInfo {
+    /// Intel: The version of architectural performance monitoring, or 0 if
+    /// it's not supported.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.leaf_a[0] as u8
+    }
+
+    /// Intel: The number of general-purpose counters per logical processor.
+    #[inline]
+    pub fn gp_counters(&self) -> u8 {
+        (self.leaf_a[0] >> 8) as u8
+    }
+
+    /// Intel: The width (in bits) of the general-purpose counters.
+    #[inline]
+    pub fn gp_counter_width(&self) -> u8 {
+        (self.leaf_a[0] >> 16) as u8
+    }
+
+    /// Intel: The number of contiguous fixed-function counters (starting at
+    /// counter 0). Always 0 before version 2.
+    #[inline]
+    pub fn fixed_counters(&self) -> u8 {
+        if self.version() < 2 {
+            0
+        } else {
+            (self.leaf_a[3] & 0x1f) as u8
+        }
+    }
+
+    /// Intel: The width (in bits) of the fixed-function counters. Always 0
+    /// before version 2.
+    #[inline]
+    pub fn fixed_counter_width(&self) -> u8 {
+        if self.version() < 2 {
+            0
+        } else {
+            (self.leaf_a[3] >> 5) as u8
+        }
+    }
+
+    /// Intel: Whether fixed-function counter `index` is supported.
+    ///
+    /// Starting with version 5, the supported counters needn't be contiguous,
+    /// so this should be preferred over comparing against `fixed_counters`.
+    #[inline]
+    pub fn fixed_counter_supported(&self, index: u32) -> bool {
+        index < u32::from(self.fixed_counters())
+            || (self.version() >= 5 && index < 32 && bit::test(self.leaf_a[2] as usize, index))
+    }
+
+    /// Intel: Whether architectural event `index` can be counted.
+    ///
+    /// The architectural events are:
+    ///
+    /// - 0: Unhalted core cycles
+    /// - 1: Instructions retired
+    /// - 2: Unhalted reference cycles
+    /// - 3: Last-level cache references
+    /// - 4: Last-level cache misses
+    /// - 5: Branch instructions retired
+    /// - 6: Branch mispredicts retired
+    /// - 7: Top-down slots
+    ///
+    /// Later events are reported as well, if the CPU enumerates them.
+    #[inline]
+    pub fn arch_event_available(&self, index: u32) -> bool {
+        // EAX[31:24] is the number of valid bits in EBX, and a *set* bit in
+        // EBX means the event is unavailable.
+        let len = self.leaf_a[0] >> 24;
+        index < len && index < 32 && !bit::test(self.leaf_a[1] as usize, index)
+    }
+
+    /// AMD: The core performance counter extensions are supported
+    /// (PerfCtrExtCore, 0x8000_0001 `ECX[23]`).
+    #[inline]
+    pub fn amd_core_counter_ext(&self) -> bool {
+        bit::test(self.ext1_ecx as usize, 23)
+    }
+
+    /// AMD: The northbridge (data fabric) performance counter extensions are
+    /// supported (PerfCtrExtNB, 0x8000_0001 `ECX[24]`).
+    #[inline]
+    pub fn amd_nb_counter_ext(&self) -> bool {
+        bit::test(self.ext1_ecx as usize, 24)
+    }
+
+    /// AMD: Performance monitoring version 2 is supported (0x8000_0022
+    /// `EAX[0]`).
+    #[inline]
+    pub fn amd_perfmon_v2(&self) -> bool {
+        bit::test(self.ext22[0] as usize, 0)
+    }
+
+    /// AMD: The number of core performance counters.
+    ///
+    /// This comes from leaf 0x8000_0022 with performance monitoring version 2,
+    /// and is otherwise 6 with the core counter extensions, or 4 without.
+    #[inline]
+    pub fn amd_core_counters(&self) -> u8 {
+        if self.amd_perfmon_v2() {
+            (self.ext22[1] & 0xf) as u8
+        } else if self.amd_core_counter_ext() {
+            6
+        } else if self.amd {
+            4
+        } else {
+            0
+        }
+    }
+
+    /// AMD: The number of northbridge (data fabric) performance counters.
+    ///
+    /// This comes from leaf 0x8000_0022 with performance monitoring version 2,
+    /// and is otherwise 4 with the northbridge counter extensions, or 0
+    /// without.
+    #[inline]
+    pub fn amd_nb_counters(&self) -> u8 {
+        if self.amd_perfmon_v2() {
+            ((self.ext22[1] >> 10) & 0x3f) as u8
+        } else if self.amd_nb_counter_ext() {
+            4
+        } else {
+            0
+        }
+    }
+}
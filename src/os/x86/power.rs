@@ -0,0 +1,213 @@
+//! Reports thermal and power management features.
+
+use super::{bit, cpuid, is_amd_like};
+
+/// The thermal and power management features of the CPU, as returned by
+/// [`power_features`].
+///
+/// These come from CPUID leaf 6 (Intel, though AMD implements a few of the
+/// bits) and leaf 0x8000_0007 EDX (mostly AMD).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PowerFeatures {
+    /// CPUID leaf 6, EAX..ECX.
+    leaf6: [u32; 3],
+    /// CPUID leaf 0x8000_0007, EDX.
+    ext7_edx: u32,
+    /// Whether this is an AMD (or Hygon) CPU.
+    amd: bool,
+}
+
+/// Returns the thermal and power management features supported by the CPU.
+///
+/// This reads leaves 0, 6 and 0x8000_0007.
+pub fn power_features() -> PowerFeatures {
+    let mut features = PowerFeatures::default();
+    if let Some(leaf) = cpuid(0x0000_0006_u32, 0) {
+        features.leaf6 = [leaf.eax, leaf.ebx, leaf.ecx];
+    }
+    if let Some(leaf) = cpuid(0x8000_0007_u32, 0) {
+        features.ext7_edx = leaf.edx;
+    }
+    features.amd = is_amd_like();
+    features
+}
+
+impl PowerFeatures {
+    #[inline]
+    fn eax(&self, b: u32) -> bool {
+        bit::test(self.leaf6[0] as usize, b)
+    }
+
+    #[inline]
+    fn ext7(&self, b: u32) -> bool {
+        bit::test(self.ext7_edx as usize, b)
+    }
+
+    /// A digital thermal sensor is available (leaf 6 `EAX[0]`).
+    #[inline]
+    pub fn digital_thermal_sensor(&self) -> bool {
+        self.eax(0)
+    }
+
+    /// Intel Turbo Boost is available (leaf 6 `EAX[1]`).
+    ///
+    /// Note that this reads as false if Turbo Boost has been disabled (for
+    /// example, in the firmware settings).
+    #[inline]
+    pub fn turbo_boost(&self) -> bool {
+        self.eax(1)
+    }
+
+    /// The APIC timer always runs at a constant rate, even in deep C-states
+    /// (ARAT, leaf 6 `EAX[2]`).
+    #[inline]
+    pub fn arat(&self) -> bool {
+        self.eax(2)
+    }
+
+    /// Power limit notification is supported (leaf 6 `EAX[4]`).
+    #[inline]
+    pub fn power_limit_notification(&self) -> bool {
+        self.eax(4)
+    }
+
+    /// Clock modulation duty cycle extension is supported (leaf 6 `EAX[5]`).
+    #[inline]
+    pub fn clock_modulation_extension(&self) -> bool {
+        self.eax(5)
+    }
+
+    /// Package thermal management is supported (leaf 6 `EAX[6]`).
+    #[inline]
+    pub fn package_thermal_management(&self) -> bool {
+        self.eax(6)
+    }
+
+    /// Hardware-controlled performance states (HWP, also known as Intel Speed
+    /// Shift) are supported (leaf 6 `EAX[7]`).
+    #[inline]
+    pub fn hwp(&self) -> bool {
+        self.eax(7)
+    }
+
+    /// HWP notifications are supported (leaf 6 `EAX[8]`).
+    #[inline]
+    pub fn hwp_notification(&self) -> bool {
+        self.eax(8)
+    }
+
+    /// The HWP activity window can be controlled (leaf 6 `EAX[9]`).
+    #[inline]
+    pub fn hwp_activity_window(&self) -> bool {
+        self.eax(9)
+    }
+
+    /// The HWP energy/performance preference can be controlled (leaf 6
+    /// `EAX[10]`).
+    #[inline]
+    pub fn hwp_energy_performance_preference(&self) -> bool {
+        self.eax(10)
+    }
+
+    /// HWP package-level requests are supported (leaf 6 `EAX[11]`).
+    #[inline]
+    pub fn hwp_package_level_request(&self) -> bool {
+        self.eax(11)
+    }
+
+    /// Hardware duty cycling is supported (HDC, leaf 6 `EAX[13]`).
+    #[inline]
+    pub fn hdc(&self) -> bool {
+        self.eax(13)
+    }
+
+    /// Intel Turbo Boost Max Technology 3.0 is available (leaf 6 `EAX[14]`).
+    #[inline]
+    pub fn turbo_boost_max_3(&self) -> bool {
+        self.eax(14)
+    }
+
+    /// Intel Thread Director is supported (leaf 6 `EAX[23]`).
+    #[inline]
+    pub fn thread_director(&self) -> bool {
+        self.eax(23)
+    }
+
+    /// The number of interrupt thresholds of the digital thermal sensor (leaf
+    /// 6 `EBX[3:0]`).
+    #[inline]
+    pub fn thermal_interrupt_thresholds(&self) -> u8 {
+        (self.leaf6[1] & 0xf) as u8
+    }
+
+    /// The `IA32_MPERF` and `IA32_APERF` MSRs are available, so the effective
+    /// frequency can be measured (leaf 6 `ECX[0]`).
+    #[inline]
+    pub fn hardware_coordination_feedback(&self) -> bool {
+        bit::test(self.leaf6[2] as usize, 0)
+    }
+
+    /// The energy/performance bias can be controlled with
+    /// `IA32_ENERGY_PERF_BIAS` (leaf 6 `ECX[3]`).
+    #[inline]
+    pub fn energy_performance_bias(&self) -> bool {
+        bit::test(self.leaf6[2] as usize, 3)
+    }
+
+    /// The time stamp counter runs at a constant rate in all P-, C- and
+    /// T-states (0x8000_0007 `EDX[8]`). This is reported by both Intel and AMD.
+    #[inline]
+    pub fn invariant_tsc(&self) -> bool {
+        self.ext7(8)
+    }
+
+    /// AMD: A temperature sensor is available (0x8000_0007 `EDX[0]`).
+    #[inline]
+    pub fn amd_temperature_sensor(&self) -> bool {
+        self.amd && self.ext7(0)
+    }
+
+    /// AMD: Hardware thermal control (HTC) is supported (0x8000_0007 `EDX[4]`).
+    #[inline]
+    pub fn amd_hardware_thermal_control(&self) -> bool {
+        self.amd && self.ext7(4)
+    }
+
+    /// AMD: Hardware P-state control is supported (0x8000_0007 `EDX[7]`).
+    #[inline]
+    pub fn amd_hardware_pstate(&self) -> bool {
+        self.amd && self.ext7(7)
+    }
+
+    /// AMD: Core performance boost is available (0x8000_0007 `EDX[9]`).
+    ///
+    /// Like [`turbo_boost`](PowerFeatures::turbo_boost), this is AMD's way of
+    /// saying the clock speed may rise above the base frequency.
+    #[inline]
+    pub fn amd_core_performance_boost(&self) -> bool {
+        self.amd && self.ext7(9)
+    }
+
+    /// AMD: Read-only aliases of the `MPERF` and `APERF` MSRs are available
+    /// (0x8000_0007 `EDX[10]`).
+    #[inline]
+    pub fn amd_effective_frequency_read_only(&self) -> bool {
+        self.amd && self.ext7(10)
+    }
+
+    /// AMD: Processor power reporting is supported (0x8000_0007 `EDX[12]`).
+    #[inline]
+    pub fn amd_power_reporting(&self) -> bool {
+        self.amd && self.ext7(12)
+    }
+
+    /// Whether the CPU may run above (or below) its base frequency on its own,
+    /// in which case benchmark results may vary with load and temperature.
+    ///
+    /// This is true if Turbo Boost, AMD's core performance boost, or HWP is
+    /// available.
+    #[inline]
+    pub fn frequency_may_scale(&self) -> bool {
+        self.turbo_boost() || self.amd_core_performance_boost() || self.hwp()
+    }
+}
@@ -0,0 +1,296 @@
+//! Reports support for Intel Resource Director Technology (RDT), and AMD's
+//! equivalent Platform Quality of Service (PQoS) extensions.
+//!
+//! Both vendors describe cache monitoring and allocation with CPUID leaves 0xF
+//! and 0x10. AMD describes its memory bandwidth enforcement with leaf
+//! 0x8000_0020 instead of Intel's MBA sub-leaf.
+
+use super::{bit, cpuid, is_amd_like};
+
+/// Information about resource monitoring and allocation, as returned by
+/// [`rdt_info`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RdtInfo {
+    monitoring: bool,
+    max_rmid: u32,
+    l3_monitoring: Option<L3MonitoringInfo>,
+    allocation: bool,
+    l3_cat: Option<CatInfo>,
+    l2_cat: Option<CatInfo>,
+    mba: Option<MbaInfo>,
+    amd_mba: Option<AmdMbaInfo>,
+}
+
+/// L3 cache monitoring capabilities, as returned by
+/// [`RdtInfo::l3_monitoring`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct L3MonitoringInfo {
+    max_rmid: u32,
+    upscaling_factor: u32,
+    counter_width: u8,
+    occupancy: bool,
+    mbm_total: bool,
+    mbm_local: bool,
+}
+
+/// Cache allocation capabilities for one cache level, as returned by
+/// [`RdtInfo::l3_cat`] and [`RdtInfo::l2_cat`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CatInfo {
+    cbm_len: u8,
+    shareable_mask: u32,
+    cdp: bool,
+    max_cos: u16,
+}
+
+/// Intel memory bandwidth allocation capabilities, as returned by
+/// [`RdtInfo::mba`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MbaInfo {
+    max_throttle: u16,
+    linear: bool,
+    max_cos: u16,
+}
+
+/// AMD memory bandwidth enforcement capabilities, as returned by
+/// [`RdtInfo::amd_mba`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AmdMbaInfo {
+    bandwidth_len: u32,
+    max_cos: u16,
+}
+
+/// Returns information about resource monitoring and allocation (cache
+/// occupancy and bandwidth monitoring, cache allocation, and memory bandwidth
+/// allocation).
+///
+/// Note that these are only configurable from kernel mode (or through an OS
+/// interface, such as Linux's `resctrl` file system).
+///
+/// This reads leaf 7, the sub-leaves of leaves 0xF and 0x10 that the CPU
+/// reports, and on AMD, leaf 0x8000_0020.
+pub fn rdt_info() -> RdtInfo {
+    let mut info = RdtInfo::default();
+    let leaf7_ebx = match cpuid(0x0000_0007_u32, 0) {
+        Some(leaf7) => leaf7.ebx,
+        None => return info,
+    };
+
+    // Monitoring (RDT-M / PQM) is leaf 7 EBX[12].
+    if bit::test(leaf7_ebx as usize, 12) {
+        if let Some(leaf) = cpuid(0x0000_000f_u32, 0) {
+            info.monitoring = true;
+            info.max_rmid = leaf.ebx;
+            // L3 monitoring is EDX[1], and is described by sub-leaf 1.
+            if bit::test(leaf.edx as usize, 1) {
+                if let Some(l3) = cpuid(0x0000_000f_u32, 1) {
+                    info.l3_monitoring = Some(L3MonitoringInfo {
+                        max_rmid: l3.ecx,
+                        upscaling_factor: l3.ebx,
+                        // EAX[7:0] is the width's offset from 24 bits.
+                        counter_width: 24 + (l3.eax & 0xff) as u8,
+                        occupancy: bit::test(l3.edx as usize, 0),
+                        mbm_total: bit::test(l3.edx as usize, 1),
+                        mbm_local: bit::test(l3.edx as usize, 2),
+                    });
+                }
+            }
+        }
+    }
+
+    // Allocation (RDT-A / PQE) is leaf 7 EBX[15].
+    if bit::test(leaf7_ebx as usize, 15) {
+        if let Some(leaf) = cpuid(0x0000_0010_u32, 0) {
+            info.allocation = true;
+            // The L3 and L2 CAT sub-leaves (1 and 2) share a layout.
+            let cat = |sub_leaf| {
+                cpuid(0x0000_0010_u32, sub_leaf).map(|r| CatInfo {
+                    cbm_len: (r.eax & 0x1f) as u8 + 1,
+                    shareable_mask: r.ebx,
+                    cdp: bit::test(r.ecx as usize, 2),
+                    max_cos: r.edx as u16,
+                })
+            };
+            if bit::test(leaf.ebx as usize, 1) {
+                info.l3_cat = cat(1);
+            }
+            if bit::test(leaf.ebx as usize, 2) {
+                info.l2_cat = cat(2);
+            }
+            if bit::test(leaf.ebx as usize, 3) {
+                info.mba = cpuid(0x0000_0010_u32, 3).map(|r| MbaInfo {
+                    max_throttle: (r.eax & 0xfff) as u16 + 1,
+                    linear: bit::test(r.ecx as usize, 2),
+                    max_cos: r.edx as u16,
+                });
+            }
+        }
+
+        // L3 memory bandwidth enforcement is 0x8000_0020 sub-leaf 0 EBX[1],
+        // and is described by sub-leaf 1.
+        if is_amd_like() {
+            let supported = match cpuid(0x8000_0020_u32, 0) {
+                Some(leaf) => bit::test(leaf.ebx as usize, 1),
+                None => false,
+            };
+            if supported {
+                info.amd_mba = cpuid(0x8000_0020_u32, 1).map(|r| AmdMbaInfo {
+                    bandwidth_len: r.eax,
+                    max_cos: r.edx as u16,
+                });
+            }
+        }
+    }
+    info
+}
+
+impl RdtInfo {
+    /// Resource monitoring is supported (RDT-M on Intel, PQM on AMD).
+    #[inline]
+    pub fn monitoring(&self) -> bool {
+        self.monitoring
+    }
+
+    /// The highest RMID (resource monitoring ID) of any resource.
+    #[inline]
+    pub fn max_rmid(&self) -> u32 {
+        self.max_rmid
+    }
+
+    /// L3 cache monitoring capabilities, if L3 monitoring is supported.
+    #[inline]
+    pub fn l3_monitoring(&self) -> Option<L3MonitoringInfo> {
+        self.l3_monitoring
+    }
+
+    /// Resource allocation is supported (RDT-A on Intel, PQE on AMD).
+    #[inline]
+    pub fn allocation(&self) -> bool {
+        self.allocation
+    }
+
+    /// L3 cache allocation capabilities, if L3 CAT is supported.
+    #[inline]
+    pub fn l3_cat(&self) -> Option<CatInfo> {
+        self.l3_cat
+    }
+
+    /// L2 cache allocation capabilities, if L2 CAT is supported.
+    #[inline]
+    pub fn l2_cat(&self) -> Option<CatInfo> {
+        self.l2_cat
+    }
+
+    /// Intel: Memory bandwidth allocation capabilities, if MBA is supported.
+    #[inline]
+    pub fn mba(&self) -> Option<MbaInfo> {
+        self.mba
+    }
+
+    /// AMD: L3 memory bandwidth enforcement capabilities, if supported.
+    #[inline]
+    pub fn amd_mba(&self) -> Option<AmdMbaInfo> {
+        self.amd_mba
+    }
+}
+
+impl L3MonitoringInfo {
+    /// The highest RMID usable for L3 monitoring.
+    #[inline]
+    pub fn max_rmid(&self) -> u32 {
+        self.max_rmid
+    }
+
+    /// The factor to multiply counter values by to convert them to bytes.
+    #[inline]
+    pub fn upscaling_factor(&self) -> u32 {
+        self.upscaling_factor
+    }
+
+    /// The width (in bits) of the monitoring counters.
+    #[inline]
+    pub fn counter_width(&self) -> u8 {
+        self.counter_width
+    }
+
+    /// L3 occupancy monitoring is supported.
+    #[inline]
+    pub fn occupancy(&self) -> bool {
+        self.occupancy
+    }
+
+    /// Total memory bandwidth monitoring is supported.
+    #[inline]
+    pub fn mbm_total(&self) -> bool {
+        self.mbm_total
+    }
+
+    /// Local memory bandwidth monitoring is supported.
+    #[inline]
+    pub fn mbm_local(&self) -> bool {
+        self.mbm_local
+    }
+}
+
+impl CatInfo {
+    /// The length (in bits) of the capacity bitmasks.
+    #[inline]
+    pub fn cbm_len(&self) -> u8 {
+        self.cbm_len
+    }
+
+    /// The bits of the capacity bitmask which may be shared with other
+    /// entities (such as integrated graphics).
+    #[inline]
+    pub fn shareable_mask(&self) -> u32 {
+        self.shareable_mask
+    }
+
+    /// Code and data prioritization (separate masks for code and data) is
+    /// supported.
+    #[inline]
+    pub fn cdp(&self) -> bool {
+        self.cdp
+    }
+
+    /// The highest class of service number.
+    #[inline]
+    pub fn max_cos(&self) -> u16 {
+        self.max_cos
+    }
+}
+
+impl MbaInfo {
+    /// The maximum throttling value.
+    #[inline]
+    pub fn max_throttle(&self) -> u16 {
+        self.max_throttle
+    }
+
+    /// Whether the throttling values are linear (so they can be treated as a
+    /// percentage).
+    #[inline]
+    pub fn linear(&self) -> bool {
+        self.linear
+    }
+
+    /// The highest class of service number.
+    #[inline]
+    pub fn max_cos(&self) -> u16 {
+        self.max_cos
+    }
+}
+
+impl AmdMbaInfo {
+    /// The width (in bits) of the bandwidth limit field.
+    #[inline]
+    pub fn bandwidth_len(&self) -> u32 {
+        self.bandwidth_len
+    }
+
+    /// The highest class of service number.
+    #[inline]
+    pub fn max_cos(&self) -> u16 {
+        self.max_cos
+    }
+}
@@ -0,0 +1,107 @@
+//! Reports support for AMD's secure virtualization features: SME (Secure Memory
+//! Encryption) and SEV (Secure Encrypted Virtualization) with its SEV-ES and
+//! SEV-SNP extensions.
+
+use super::{bit, cpuid, is_amd_like};
+
+/// Information about AMD's memory encryption features, as returned by
+/// [`sev_info`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SevInfo {
+    /// CPUID leaf 0x8000_001F, EAX..EDX.
+    leaf: [u32; 4],
+    /// CPUID leaf 1, `ECX[31]` (a hypervisor is present).
+    hypervisor: bool,
+}
+
+/// Returns information about SME and SEV, or `None` if this isn't an AMD (or
+/// Hygon) CPU, or it doesn't report these features.
+///
+/// This reads leaf 0 (for the vendor), leaf 0x8000_001F, and leaf 1 (for
+/// [`SevInfo::likely_guest`]).
+pub fn sev_info() -> Option<SevInfo> {
+    if !is_amd_like() {
+        return None;
+    }
+    let leaf = cpuid(0x8000_001f_u32, 0)?;
+    let hypervisor = match cpuid(0x0000_0001_u32, 0) {
+        Some(leaf1) => bit::test(leaf1.ecx as usize, 31),
+        None => false,
+    };
+    Some(SevInfo {
+        leaf: [leaf.eax, leaf.ebx, leaf.ecx, leaf.edx],
+        hypervisor,
+    })
+}
+
+impl SevInfo {
+    #[inline]
+    fn eax(&self, b: u32) -> bool {
+        bit::test(self.leaf[0] as usize, b)
+    }
+
+    /// Secure Memory Encryption is supported (`EAX[0]`).
+    #[inline]
+    pub fn sme(&self) -> bool {
+        self.eax(0)
+    }
+
+    /// Secure Encrypted Virtualization is supported (`EAX[1]`).
+    #[inline]
+    pub fn sev(&self) -> bool {
+        self.eax(1)
+    }
+
+    /// SEV with Encrypted State (register state is encrypted too) is supported
+    /// (`EAX[3]`).
+    #[inline]
+    pub fn sev_es(&self) -> bool {
+        self.eax(3)
+    }
+
+    /// SEV with Secure Nested Paging (integrity protection) is supported
+    /// (`EAX[4]`).
+    #[inline]
+    pub fn sev_snp(&self) -> bool {
+        self.eax(4)
+    }
+
+    /// The position of the C-bit (the page table entry bit which marks a
+    /// page as encrypted) (`EBX[5:0]`).
+    #[inline]
+    pub fn c_bit_position(&self) -> u8 {
+        (self.leaf[1] & 0x3f) as u8
+    }
+
+    /// The number of physical address bits lost when memory encryption is
+    /// enabled (`EBX[11:6]`).
+    #[inline]
+    pub fn physical_address_reduction(&self) -> u8 {
+        ((self.leaf[1] >> 6) & 0x3f) as u8
+    }
+
+    /// The number of encrypted guests supported simultaneously (ECX).
+    #[inline]
+    pub fn max_encrypted_guests(&self) -> u32 {
+        self.leaf[2]
+    }
+
+    /// The minimum ASID of guests which use SEV but not SEV-ES (EDX). Lower
+    /// ASIDs are reserved for SEV-ES and SEV-SNP guests.
+    #[inline]
+    pub fn min_sev_no_es_asid(&self) -> u32 {
+        self.leaf[3]
+    }
+
+    /// Returns true if we're most likely running inside an SEV guest.
+    ///
+    /// The definitive answer is in the `SEV_STATUS` MSR, which can only be
+    /// read from kernel mode. Instead, this checks that we're running under a
+    /// hypervisor which advertises SEV to us, which hypervisors generally only
+    /// do for SEV guests. Since the hypervisor controls both, this shouldn't be
+    /// relied on for security decisions (use attestation for that).
+    #[inline]
+    pub fn likely_guest(&self) -> bool {
+        self.hypervisor && self.sev()
+    }
+}
@@ -0,0 +1,176 @@
+//! Reports the XSAVE state components the CPU supports and the OS has enabled,
+//! along with their layout in the XSAVE area.
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+use super::{bit, cpuid};
+
+/// Information about the XSAVE feature set, as returned by [`xsave_info`].
+///
+/// This is a snapshot of `XCR0` and CPUID leaf 0xD (sub-leaves 0 and 1). The
+/// layout of individual state components can be queried with
+/// [`XsaveInfo::component`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct XsaveInfo {
+    xcr0: u64,
+    user_mask: u64,
+    supervisor_mask: u64,
+    enabled_size: u32,
+    max_size: u32,
+    compacted_size: u32,
+}
+
+/// The location and properties of a single XSAVE state component, as returned
+/// by [`XsaveInfo::component`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct XsaveComponent {
+    size: u32,
+    offset: u32,
+    supervisor: bool,
+    aligned: bool,
+}
+
+/// Returns information about XSAVE state components, or `None` if the CPU
+/// doesn't support XSAVE or the OS hasn't enabled it (that is, `OSXSAVE` is
+/// not set, in which case `xgetbv` would fault).
+///
+/// This reads leaf 1, sub-leaves 0 and 1 of leaf 0xD, and `XCR0`.
+pub fn xsave_info() -> Option<XsaveInfo> {
+    let CpuidResult { ecx, .. } = cpuid(0x0000_0001_u32, 0)?;
+    // XSAVE is ECX[26], OSXSAVE is ECX[27].
+    if !bit::test(ecx as usize, 26) || !bit::test(ecx as usize, 27) {
+        return None;
+    }
+    let leaf0 = cpuid(0x0000_000d_u32, 0)?;
+    let leaf1 = cpuid(0x0000_000d_u32, 1)?;
+    // This is safe because the CPU supports `xsave` and the OS has set
+    // `osxsave`.
+    let xcr0 = unsafe { _xgetbv(0) };
+    Some(XsaveInfo {
+        xcr0,
+        user_mask: u64::from(leaf0.eax) | u64::from(leaf0.edx) << 32,
+        supervisor_mask: u64::from(leaf1.ecx) | u64::from(leaf1.edx) << 32,
+        enabled_size: leaf0.ebx,
+        max_size: leaf0.ecx,
+        compacted_size: leaf1.ebx,
+    })
+}
+
+impl XsaveInfo {
+    /// The value of `XCR0`, that is, the user state components the OS has
+    /// enabled.
+    #[inline]
+    pub fn xcr0(&self) -> u64 {
+        self.xcr0
+    }
+
+    /// The user state components the CPU supports, that is, the bits which
+    /// may be set in `XCR0`.
+    #[inline]
+    pub fn supported_user_components(&self) -> u64 {
+        self.user_mask
+    }
+
+    /// The supervisor state components the CPU supports, that is, the bits
+    /// which may be set in the `IA32_XSS` MSR.
+    #[inline]
+    pub fn supported_supervisor_components(&self) -> u64 {
+        self.supervisor_mask
+    }
+
+    /// The size (in bytes) of the XSAVE area needed by `xsave` for the
+    /// components currently enabled in `XCR0`, using the standard format.
+    #[inline]
+    pub fn enabled_size(&self) -> u32 {
+        self.enabled_size
+    }
+
+    /// The size (in bytes) of the XSAVE area needed by `xsave` if every
+    /// supported user state component were enabled.
+    #[inline]
+    pub fn max_size(&self) -> u32 {
+        self.max_size
+    }
+
+    /// The size (in bytes) of the XSAVE area needed by `xsaves` for the
+    /// components enabled in `XCR0 | IA32_XSS`, using the compacted format.
+    ///
+    /// Only meaningful if `xsaves` or `xsavec` is supported.
+    #[inline]
+    pub fn compacted_size(&self) -> u32 {
+        self.compacted_size
+    }
+
+    /// Returns the layout of state component `index` (e.g. 2 for AVX, 9 for
+    /// PKRU), or `None` if the CPU doesn't support it.
+    ///
+    /// The x87 and SSE components (0 and 1) live in the legacy region of the
+    /// XSAVE area, and are always reported.
+    ///
+    /// Components other than x87 and SSE are read from leaf 0xD, sub-leaf
+    /// `index`, when this is called.
+    pub fn component(&self, index: u32) -> Option<XsaveComponent> {
+        match index {
+            0 => Some(XsaveComponent {
+                size: 160,
+                offset: 0,
+                supervisor: false,
+                aligned: false,
+            }),
+            1 => Some(XsaveComponent {
+                size: 256,
+                offset: 160,
+                supervisor: false,
+                aligned: false,
+            }),
+            2..=62 => {
+                let supported = self.user_mask | self.supervisor_mask;
+                if supported & (1 << index) == 0 {
+                    return None;
+                }
+                let CpuidResult { eax, ebx, ecx, .. } = cpuid(0x0000_000d_u32, index)?;
+                Some(XsaveComponent {
+                    size: eax,
+                    offset: ebx,
+                    supervisor: bit::test(ecx as usize, 0),
+                    aligned: bit::test(ecx as usize, 1),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl XsaveComponent {
+    /// The size (in bytes) of this component.
+    #[inline]
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// The offset (in bytes) of this component from the start of an XSAVE area
+    /// in the standard (non-compacted) format.
+    ///
+    /// This is always 0 for supervisor components, which only appear in the
+    /// compacted format.
+    #[inline]
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Whether this is a supervisor state component (managed through
+    /// `IA32_XSS`) rather than a user one (managed through `XCR0`).
+    #[inline]
+    pub fn is_supervisor(&self) -> bool {
+        self.supervisor
+    }
+
+    /// Whether this component is aligned to 64 bytes in the compacted format.
+    #[inline]
+    pub fn is_aligned(&self) -> bool {
+        self.aligned
+    }
+}
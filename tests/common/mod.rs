@@ -0,0 +1,27 @@
+//! Helpers shared by several test binaries.
+
+/// Resets and redetects the cache from several threads, while checking that
+/// `snapshot()` always sees the same features on this one.
+pub fn redetect_and_reset_cache_concurrently() {
+    let before = core_detect::snapshot();
+    let writers: Vec<_> = (0..4)
+        .map(|i| {
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    if i % 2 == 0 {
+                        core_detect::reset_cache();
+                    } else {
+                        core_detect::redetect();
+                    }
+                }
+            })
+        })
+        .collect();
+    for _ in 0..1000 {
+        assert_eq!(core_detect::snapshot(), before);
+    }
+    for w in writers {
+        w.join().unwrap();
+    }
+    assert_eq!(core_detect::snapshot(), before);
+}
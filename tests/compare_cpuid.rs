@@ -0,0 +1,371 @@
+//! Checks detection against the raw `cpuid` bits, for the features and leaves
+//! that cupid doesn't know about.
+#![cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+// `__cpuid_count` is safe on newer compilers.
+#![allow(unused_unsafe)]
+
+#[cfg(target_arch = "x86")]
+use std::arch::x86::{__cpuid_count, CpuidResult};
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::{__cpuid_count, CpuidResult};
+
+/// Queries `leaf`/`subleaf`, or returns `None` if `leaf` is above the highest
+/// supported basic or extended leaf.
+fn cpuid(leaf: u32, subleaf: u32) -> Option<CpuidResult> {
+    let max_leaf = unsafe { __cpuid_count(leaf & 0x8000_0000, 0) }.eax;
+    if leaf <= max_leaf {
+        Some(unsafe { __cpuid_count(leaf, subleaf) })
+    } else {
+        None
+    }
+}
+
+/// Returns whether `bit` of `reg` is set.
+fn bit(reg: u32, bit: u32) -> bool {
+    reg & (1 << bit) != 0
+}
+
+/// Checks that each feature is detected exactly when its `cpuid` bit is set.
+macro_rules! check_features {
+    ($($feature:tt: $cpuid_bit:expr),* $(,)?) => {$(
+        assert_eq!(
+            core_detect::is_x86_feature_detected!($feature),
+            $cpuid_bit,
+            "{}",
+            $feature
+        );
+    )*};
+}
+
+/// Returns EAX of leaf 7 sub-leaf 1, or 0 if that sub-leaf isn't supported.
+fn leaf_7_1_eax() -> u32 {
+    // The max sub-leaf is returned in EAX for sub-leaf 0.
+    if cpuid(7, 0).map_or(0, |r| r.eax) >= 1 {
+        cpuid(7, 1).map_or(0, |r| r.eax)
+    } else {
+        0
+    }
+}
+
+/// Checks an AVX-512 feature against its `cpuid` bit. These also need the OS
+/// to save the AVX-512 state, which we infer from `avx512f` being detected.
+macro_rules! check_avx512_feature {
+    ($feature:tt, $cpuid_bit:expr) => {
+        let detected = core_detect::is_x86_feature_detected!($feature);
+        let cpuid_bit = $cpuid_bit;
+        if detected {
+            assert!(
+                cpuid_bit,
+                "{} detected but its cpuid bit is clear",
+                $feature
+            );
+        }
+        if core_detect::is_x86_feature_detected!("avx512f") && cpuid_bit {
+            assert!(
+                detected,
+                "{} not detected but its cpuid bit is set",
+                $feature
+            );
+        }
+    };
+}
+
+#[test]
+fn avx512bf16_uses_leaf_7_subleaf_1() {
+    check_avx512_feature!("avx512bf16", bit(leaf_7_1_eax(), 5));
+}
+
+#[test]
+fn avx512vp2intersect_uses_leaf_7_edx() {
+    let leaf_7_edx = cpuid(7, 0).map_or(0, |r| r.edx);
+    check_avx512_feature!("avx512vp2intersect", bit(leaf_7_edx, 8));
+}
+
+#[test]
+fn address_sizes_match_leaf_8000_0008() {
+    let (phys_bits, virt_bits, guest_phys_bits) = core_detect::address_sizes();
+    if let Some(leaf) = cpuid(0x8000_0008, 0) {
+        assert_eq!(u32::from(phys_bits), leaf.eax & 0xff);
+        assert_eq!(u32::from(virt_bits), (leaf.eax >> 8) & 0xff);
+        // A guest physical address size of 0 means it's the same as the
+        // physical address size.
+        match (leaf.eax >> 16) & 0xff {
+            0 => assert_eq!(guest_phys_bits, phys_bits),
+            n => assert_eq!(u32::from(guest_phys_bits), n),
+        }
+    } else {
+        // Without the leaf, PAE (EDX[6]) or PSE-36 (EDX[17]) mean 36 bits.
+        let edx = cpuid(1, 0).map_or(0, |r| r.edx);
+        let expected = if bit(edx, 6) || bit(edx, 17) { 36 } else { 32 };
+        assert_eq!(phys_bits, expected);
+        assert_eq!(virt_bits, 32);
+        assert_eq!(guest_phys_bits, phys_bits);
+    }
+}
+
+#[test]
+fn la57_matches_leaf_7() {
+    check_features! {
+        "la57": bit(cpuid(7, 0).map_or(0, |r| r.ecx), 16),
+    }
+}
+
+#[test]
+fn mitigation_caps_match_leaf_7() {
+    let caps = core_detect::mitigation_caps();
+    let edx = cpuid(7, 0).map_or(0, |r| r.edx);
+    assert_eq!(caps.md_clear(), bit(edx, 10));
+    assert_eq!(caps.ibrs_ibpb(), bit(edx, 26));
+    assert_eq!(caps.stibp(), bit(edx, 27));
+    assert_eq!(caps.l1d_flush(), bit(edx, 28));
+    assert_eq!(caps.arch_capabilities(), bit(edx, 29));
+    assert_eq!(caps.ssbd(), bit(edx, 31));
+}
+
+#[test]
+fn core_type_matches_leaf_1a() {
+    let hybrid = bit(cpuid(7, 0).map_or(0, |r| r.edx), 15);
+    if !hybrid {
+        assert_eq!(core_detect::core_type(), None);
+        return;
+    }
+    // The thread can migrate to another kind of core between the queries, so
+    // only compare when leaf 0x1A reads the same before and after.
+    for _ in 0..100 {
+        let before = cpuid(0x1a, 0).map_or(0, |r| r.eax);
+        let result = core_detect::core_type_and_model_id();
+        let after = cpuid(0x1a, 0).map_or(0, |r| r.eax);
+        if before != after {
+            continue;
+        }
+        if before == 0 {
+            assert_eq!(result, None);
+        } else {
+            let (ty, model_id) = result.unwrap();
+            let expected = match before >> 24 {
+                0x20 => core_detect::CoreType::Efficiency,
+                0x40 => core_detect::CoreType::Performance,
+                other => core_detect::CoreType::Other(other as u8),
+            };
+            assert_eq!(ty, expected);
+            assert_eq!(model_id, before & 0x00ff_ffff);
+        }
+        return;
+    }
+    panic!("leaf 0x1A never read the same twice in a row");
+}
+
+#[test]
+fn pmu_info_matches_leaf_a() {
+    let pmu = core_detect::pmu_info();
+    let leaf = cpuid(0xa, 0).map_or([0; 4], |r| [r.eax, r.ebx, r.ecx, r.edx]);
+    assert_eq!(u32::from(pmu.version()), leaf[0] & 0xff);
+    assert_eq!(u32::from(pmu.gp_counters()), (leaf[0] >> 8) & 0xff);
+    assert_eq!(u32::from(pmu.gp_counter_width()), (leaf[0] >> 16) & 0xff);
+    if pmu.version() >= 2 {
+        assert_eq!(u32::from(pmu.fixed_counters()), leaf[3] & 0x1f);
+        assert_eq!(u32::from(pmu.fixed_counter_width()), (leaf[3] >> 5) & 0xff);
+    }
+}
+
+/// Returns the 12-byte signature in EBX, EDX and ECX (in that order), as
+/// leaves 0 and 0x21 use.
+fn signature(leaf: &CpuidResult) -> [u8; 12] {
+    let mut signature = [0; 12];
+    signature[0..4].copy_from_slice(&leaf.ebx.to_ne_bytes());
+    signature[4..8].copy_from_slice(&leaf.edx.to_ne_bytes());
+    signature[8..12].copy_from_slice(&leaf.ecx.to_ne_bytes());
+    signature
+}
+
+/// Returns the 12-byte vendor string from leaf 0.
+fn vendor() -> [u8; 12] {
+    signature(&unsafe { __cpuid_count(0, 0) })
+}
+
+#[test]
+fn sev_info_matches_leaf_8000_001f() {
+    let info = core_detect::sev_info();
+    let vendor = vendor();
+    if vendor != *b"AuthenticAMD" && vendor != *b"HygonGenuine" {
+        assert_eq!(info, None);
+        return;
+    }
+    let leaf = cpuid(0x8000_001f, 0);
+    assert_eq!(info.is_some(), leaf.is_some());
+    if let (Some(info), Some(leaf)) = (info, leaf) {
+        assert_eq!(info.sme(), bit(leaf.eax, 0));
+        assert_eq!(info.sev(), bit(leaf.eax, 1));
+        assert_eq!(info.sev_es(), bit(leaf.eax, 3));
+        assert_eq!(info.sev_snp(), bit(leaf.eax, 4));
+        assert_eq!(u32::from(info.c_bit_position()), leaf.ebx & 0x3f);
+        assert_eq!(
+            u32::from(info.physical_address_reduction()),
+            (leaf.ebx >> 6) & 0x3f
+        );
+        assert_eq!(info.max_encrypted_guests(), leaf.ecx);
+        assert_eq!(info.min_sev_no_es_asid(), leaf.edx);
+    }
+}
+
+#[test]
+fn rdt_info_matches_leaves_f_and_10() {
+    let info = core_detect::rdt_info();
+    let leaf7_ebx = cpuid(7, 0).map_or(0, |r| r.ebx);
+
+    let leaf_f = cpuid(0xf, 0).filter(|_| bit(leaf7_ebx, 12));
+    assert_eq!(info.monitoring(), leaf_f.is_some());
+    if let Some(leaf) = leaf_f {
+        assert_eq!(info.max_rmid(), leaf.ebx);
+        assert_eq!(info.l3_monitoring().is_some(), bit(leaf.edx, 1));
+    } else {
+        assert_eq!(info.l3_monitoring(), None);
+    }
+
+    let leaf_10 = cpuid(0x10, 0).filter(|_| bit(leaf7_ebx, 15));
+    assert_eq!(info.allocation(), leaf_10.is_some());
+    let resources = leaf_10.map_or(0, |r| r.ebx);
+    assert_eq!(info.l3_cat().is_some(), bit(resources, 1));
+    assert_eq!(info.l2_cat().is_some(), bit(resources, 2));
+    assert_eq!(info.mba().is_some(), bit(resources, 3));
+}
+
+#[test]
+fn tsx_matches_leaf_7() {
+    let leaf = cpuid(7, 0);
+    let (ebx, edx) = (leaf.map_or(0, |r| r.ebx), leaf.map_or(0, |r| r.edx));
+    // RTM_ALWAYS_ABORT (EDX[11]) means every transaction aborts.
+    let rtm = bit(ebx, 11) && !bit(edx, 11);
+    assert_eq!(core_detect::is_x86_feature_detected!("hle"), bit(ebx, 4));
+    assert_eq!(core_detect::is_x86_feature_detected!("rtm"), rtm);
+    assert_eq!(
+        core_detect::is_x86_feature_detected!("tsxldtrk"),
+        rtm && bit(edx, 16)
+    );
+}
+
+#[test]
+fn cet_matches_leaf_7() {
+    let leaf = cpuid(7, 0);
+    assert_eq!(
+        core_detect::is_x86_feature_detected!("shstk"),
+        bit(leaf.map_or(0, |r| r.ecx), 7)
+    );
+    assert_eq!(
+        core_detect::is_x86_feature_detected!("ibt"),
+        bit(leaf.map_or(0, |r| r.edx), 20)
+    );
+}
+
+#[test]
+#[cfg(all(feature = "linux_syscalls", target_os = "linux"))]
+fn shstk_enabled_needs_cpu_support() {
+    let enabled = core_detect::shstk_enabled();
+    // Shadow stacks can't be on if the CPU doesn't have them, and kernels which
+    // don't know about `ARCH_SHSTK_STATUS` fail it with `EINVAL`.
+    if !bit(cpuid(7, 0).map_or(0, |r| r.ecx), 7) {
+        assert!(!enabled);
+    }
+    // Kernels with user shadow stack support list what's enabled here.
+    let status = std::fs::read_to_string("/proc/self/status").unwrap();
+    if let Some(line) = status
+        .lines()
+        .find(|line| line.starts_with("x86_Thread_features:"))
+    {
+        assert_eq!(enabled, line.split_whitespace().any(|f| f == "shstk"));
+    }
+    // It's per-thread state, so asking again gives the same answer.
+    assert_eq!(core_detect::shstk_enabled(), enabled);
+}
+
+#[test]
+fn sgx_info_matches_leaf_12() {
+    let info = core_detect::sgx_info();
+    let leaf7 = cpuid(7, 0);
+    if !bit(leaf7.map_or(0, |r| r.ebx), 2) {
+        assert_eq!(info, None);
+        return;
+    }
+    let info = info.unwrap();
+    let leaf12 = cpuid(0x12, 0).map_or([0; 2], |r| [r.eax, r.edx]);
+    assert_eq!(info.sgx1(), bit(leaf12[0], 0));
+    assert_eq!(info.sgx2(), bit(leaf12[0], 1));
+    assert_eq!(info.flc(), bit(leaf7.map_or(0, |r| r.ecx), 30));
+    assert_eq!(u32::from(info.max_enclave_size_log2_32()), leaf12[1] & 0xff);
+    assert_eq!(
+        u32::from(info.max_enclave_size_log2_64()),
+        (leaf12[1] >> 8) & 0xff
+    );
+
+    // Sub-leaves 2 and up describe the EPC sections, until one whose type
+    // (EAX[3:0]) isn't 1.
+    for index in 0.. {
+        let section = info.epc_section(index);
+        let leaf = match cpuid(0x12, index + 2) {
+            Some(leaf) if leaf.eax & 0xf == 1 => leaf,
+            _ => {
+                assert_eq!(section, None);
+                break;
+            }
+        };
+        let section = section.unwrap();
+        // Bits [31:12] of the base and size are in EAX and ECX, and bits
+        // [51:32] in EBX[19:0] and EDX[19:0].
+        let base = (u64::from(leaf.ebx & 0xf_ffff) << 32) + u64::from(leaf.eax >> 12 << 12);
+        let size = (u64::from(leaf.edx & 0xf_ffff) << 32) + u64::from(leaf.ecx >> 12 << 12);
+        assert_eq!(section.base(), base);
+        assert_eq!(section.size(), size);
+    }
+}
+
+#[test]
+fn is_tdx_guest_matches_leaf_21() {
+    let expected = match cpuid(0x21, 0) {
+        Some(leaf) => signature(&leaf) == *b"IntelTDX    ",
+        None => false,
+    };
+    assert_eq!(core_detect::is_tdx_guest(), expected);
+}
+
+#[test]
+fn timing_and_wait_features_match_cpuid() {
+    let leaf7 = cpuid(7, 0);
+    let (ecx, edx) = (leaf7.map_or(0, |r| r.ecx), leaf7.map_or(0, |r| r.edx));
+    check_features! {
+        "rdtscp": bit(cpuid(0x8000_0001, 0).map_or(0, |r| r.edx), 27),
+        "rdpid": bit(ecx, 22),
+        "waitpkg": bit(ecx, 5),
+        "serialize": bit(edx, 14),
+        "uintr": bit(edx, 5),
+        "hreset": bit(leaf_7_1_eax(), 22),
+    }
+}
+
+#[test]
+fn cache_and_store_features_match_cpuid() {
+    let leaf1_edx = cpuid(1, 0).map_or(0, |r| r.edx);
+    let leaf7 = cpuid(7, 0);
+    let (ebx, ecx) = (leaf7.map_or(0, |r| r.ebx), leaf7.map_or(0, |r| r.ecx));
+    // Leaf 0x14 is only valid if the CPU has Intel PT (leaf 7 EBX[25]).
+    let leaf14_ebx = cpuid(0x14, 0).filter(|_| bit(ebx, 25)).map_or(0, |r| r.ebx);
+    check_features! {
+        "clflush": bit(leaf1_edx, 19),
+        "clflushopt": bit(ebx, 23),
+        "clwb": bit(ebx, 24),
+        "cldemote": bit(ecx, 25),
+        "movdiri": bit(ecx, 27),
+        "movdir64b": bit(ecx, 28),
+        "enqcmd": bit(ecx, 29),
+        "ptwrite": bit(leaf14_ebx, 4),
+        "wbnoinvd": bit(cpuid(0x8000_0008, 0).map_or(0, |r| r.ebx), 9),
+    }
+
+    // Leaf 1 EBX[15:8] is the line size in 8-byte units.
+    let line_size = (cpuid(1, 0).map_or(0, |r| r.ebx) >> 8 & 0xff) as usize * 8;
+    let expected = if bit(leaf1_edx, 19) {
+        Some(line_size)
+    } else {
+        None
+    };
+    assert_eq!(core_detect::clflush_line_size(), expected);
+}
@@ -1,6 +1,11 @@
 #[test]
 #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
 fn compare_with_cupid() {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::__cpuid_count;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::__cpuid_count;
+
     let information = cupid::master().unwrap();
     macro_rules! check_feature {
         ($core_detect_expr:expr, $cupid_expr:expr $(,)?) => {
@@ -171,8 +176,104 @@ fn compare_with_cupid() {
         core_detect::is_x86_feature_detected!("adx"),
         information.adx(),
     );
+    // We also report no `rtm` if the CPU sets RTM_ALWAYS_ABORT (leaf 7 EDX
+    // bit 11), which cupid doesn't know about.
+    #[allow(unused_unsafe)] // `__cpuid_count` is safe on newer compilers
+    let rtm_always_abort = unsafe { __cpuid_count(7, 0) }.edx & (1 << 11) != 0;
     check_feature!(
         core_detect::is_x86_feature_detected!("rtm"),
-        information.rtm(),
+        information.rtm() && !rtm_always_abort,
     );
+    check_feature!(
+        core_detect::is_x86_feature_detected!("hle"),
+        information.hle(),
+    );
+    check_feature!(
+        core_detect::is_x86_feature_detected!("rdtscp"),
+        information.rdtscp_and_ia32_tsc_aux(),
+    );
+    check_feature!(
+        core_detect::is_x86_feature_detected!("clflush"),
+        information.clfsh(),
+    );
+    check_feature!(
+        core_detect::is_x86_feature_detected!("clflushopt"),
+        information.clflushopt(),
+    );
+    check_feature!(
+        core_detect::is_x86_feature_detected!("clwb"),
+        information.clwb(),
+    );
+    check_feature!(
+        core_detect::is_x86_feature_detected!("pku"),
+        information.pku() && information.ospke(),
+    );
+    check_feature!(
+        core_detect::is_x86_feature_detected!("ospke"),
+        information.ospke(),
+    );
+    check_feature!(
+        core_detect::clflush_line_size().is_some(),
+        information.clfsh(),
+    );
+    check_feature!(core_detect::sgx_info().is_some(), information.sgx());
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+fn compare_xsave_info_with_cupid() {
+    let information = cupid::master().unwrap();
+    let info = core_detect::xsave_info();
+    assert_eq!(info.is_some(), information.osxsave());
+    if let (Some(info), Some(state)) = (info, information.processor_extended_state()) {
+        assert_eq!(
+            info.enabled_size(),
+            state.maximum_bytes_for_enabled_features()
+        );
+        assert_eq!(
+            info.max_size(),
+            state.maximum_bytes_for_supported_features()
+        );
+        // x87 and SSE state are always enabled if XSAVE is.
+        assert_eq!(info.xcr0() & 0b11, 0b11);
+        if info.xcr0() & 0b100 != 0 {
+            let avx = info.component(2).unwrap();
+            assert_eq!((avx.offset(), avx.size()), (576, 256));
+        }
+    }
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+fn compare_address_sizes_with_cupid() {
+    let information = cupid::master().unwrap();
+    if let Some(sizes) = information.physical_address_size() {
+        let (phys_bits, virt_bits, _) = core_detect::address_sizes();
+        assert_eq!(u32::from(phys_bits), sizes.physical_address_bits());
+        assert_eq!(u32::from(virt_bits), sizes.linear_address_bits());
+    }
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+fn compare_power_features_with_cupid() {
+    let information = cupid::master().unwrap();
+    let power = core_detect::power_features();
+    if information.thermal_power_management_information().is_some() {
+        assert_eq!(
+            power.digital_thermal_sensor(),
+            information.digital_temperature_sensor()
+        );
+        assert_eq!(power.turbo_boost(), information.intel_turbo_boost());
+        assert_eq!(power.arat(), information.arat());
+        assert_eq!(power.hwp(), information.hwp());
+        assert_eq!(power.hdc(), information.hdc());
+        assert_eq!(
+            power.hardware_coordination_feedback(),
+            information.hardware_coordination_feedback()
+        );
+    }
+    if let Some(tsc) = information.time_stamp_counter() {
+        assert_eq!(power.invariant_tsc(), tsc.invariant_tsc());
+    }
 }
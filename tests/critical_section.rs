@@ -0,0 +1,54 @@
+//! Tests the cache backend used with the `critical-section` feature.
+//!
+//! Anything linked with that feature needs a critical section implementation,
+//! and this test provides its own, so it has to be run by itself (see the
+//! comment in `Cargo.toml`).
+
+mod common;
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// A global spin lock, which also counts how often it's been taken. This is
+/// enough for the cache, which never nests critical sections.
+struct SpinLock;
+critical_section::set_impl!(SpinLock);
+
+static LOCKED: AtomicBool = AtomicBool::new(false);
+static ACQUIRED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl critical_section::Impl for SpinLock {
+    unsafe fn acquire() -> critical_section::RawRestoreState {
+        while LOCKED
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            std::hint::spin_loop();
+        }
+        ACQUIRED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    unsafe fn release(_: critical_section::RawRestoreState) {
+        LOCKED.store(false, Ordering::Release);
+    }
+}
+
+#[test]
+fn cache_uses_critical_section() {
+    let before = ACQUIRED.load(Ordering::Relaxed);
+    core_detect::initialize();
+    assert!(ACQUIRED.load(Ordering::Relaxed) > before);
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    {
+        // `tsc` can't be enabled at compile time, so this always reads the
+        // cache.
+        let before = ACQUIRED.load(Ordering::Relaxed);
+        let tsc = core_detect::is_x86_feature_detected!("tsc");
+        assert!(ACQUIRED.load(Ordering::Relaxed) > before);
+        assert_eq!(tsc, std::is_x86_feature_detected!("tsc"));
+    }
+}
+
+#[test]
+fn concurrent_redetect_and_reset_cache() {
+    common::redetect_and_reset_cache_concurrently();
+}
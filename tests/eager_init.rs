@@ -0,0 +1,33 @@
+//! Checks that with `eager_init`, the cache is filled before `main` runs. This
+//! is its own test binary without the test harness, so that nothing else can
+//! fill the cache first. It also needs the internal `__test_hooks` feature, to
+//! look at the cache.
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly",
+    target_os = "illumos",
+    target_os = "solaris",
+))]
+fn main() {
+    assert!(
+        core_detect::__cache_is_initialized(),
+        "the cache should have been filled before main",
+    );
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly",
+    target_os = "illumos",
+    target_os = "solaris",
+)))]
+fn main() {}
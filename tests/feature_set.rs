@@ -0,0 +1,94 @@
+//! Tests for `FeatureSet`, and the functions which read or update the whole
+//! cache at once.
+
+mod common;
+
+#[test]
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+fn compile_time_features_match_cfg() {
+    let features = core_detect::compile_time_features();
+    assert_eq!(features.contains("sse2"), cfg!(target_feature = "sse2"));
+    assert_eq!(features.contains("avx2"), cfg!(target_feature = "avx2"));
+    assert_eq!(features.contains("fma"), cfg!(target_feature = "fma"));
+    assert!(!features.contains("tsc"));
+    if !cfg!(allow_false_negative) {
+        // We're running, so the CPU must support everything we were built for.
+        assert!(core_detect::missing_at_runtime().is_empty());
+    }
+}
+
+#[test]
+fn compile_time_features_are_supported() {
+    // This runs, so the CPU supports everything it was built for (unless we're
+    // in an emulator which only pretends to).
+    if !cfg!(allow_false_negative) {
+        assert_eq!(core_detect::check_compile_time_features(), Ok(()));
+        core_detect::ensure_compile_time_features();
+    }
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+fn missing_features_message() {
+    use core_detect::{Feature, FeatureSet, MissingFeatures};
+    let required: FeatureSet = [Feature::sse, Feature::sse2, Feature::avx2, Feature::fma]
+        .iter()
+        .cloned()
+        .collect();
+    let missing: FeatureSet = [Feature::fma, Feature::avx2].iter().cloned().collect();
+    let report = MissingFeatures::new(required, missing);
+    assert_eq!(report.required(), required);
+    assert_eq!(report.missing(), missing);
+    assert_eq!(
+        report.to_string(),
+        "this binary requires sse, sse2, avx2, fma; this CPU lacks avx2, fma",
+    );
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+fn feature_names_round_trip() {
+    use core_detect::Feature;
+    for name in core_detect::snapshot().iter() {
+        assert_eq!(Feature::from_name(name).map(Feature::name), Some(name));
+    }
+    assert_eq!(Feature::from_name("sse4.1"), Some(Feature::sse4_1));
+    assert_eq!(Feature::from_name("abm"), Some(Feature::lzcnt));
+    assert_eq!(Feature::from_name("sse4_1"), None);
+    assert_eq!(Feature::_last.name(), "");
+}
+
+#[test]
+#[cfg_attr(
+    all(core_detect_unsafe_single_threaded, not(feature = "critical-section")),
+    ignore
+)]
+fn snapshot_is_consistent_across_threads() {
+    let threads: Vec<_> = (0..8)
+        .map(|_| std::thread::spawn(core_detect::snapshot))
+        .collect();
+    let snapshots: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    for s in &snapshots {
+        assert_eq!(*s, snapshots[0]);
+    }
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    {
+        assert_eq!(
+            snapshots[0].contains("sse2"),
+            std::is_x86_feature_detected!("sse2")
+        );
+    }
+}
+
+#[test]
+#[cfg_attr(
+    all(core_detect_unsafe_single_threaded, not(feature = "critical-section")),
+    ignore
+)]
+fn redetect_and_reset_cache() {
+    common::redetect_and_reset_cache_concurrently();
+    let before = core_detect::snapshot();
+    core_detect::reset_cache();
+    assert_eq!(core_detect::snapshot(), before);
+    assert_eq!(core_detect::redetect(), before);
+}
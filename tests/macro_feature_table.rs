@@ -0,0 +1,60 @@
+//! Checks that `attr::multiversion`'s table of feature names (in
+//! `core_detect_macros`, which can't depend on this crate) lists exactly the
+//! features `is_x86_feature_detected!` knows about.
+#![cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+
+use std::collections::BTreeSet;
+
+/// Returns the first string literal in `line`.
+fn first_string(line: &str) -> &str {
+    line.split('"').nth(1).expect("no string literal")
+}
+
+/// The names in `features!` in `src/arch/x86.rs`, including aliases such as
+/// `"abm"`, but not the names which can't be detected at runtime.
+fn detectable_names() -> BTreeSet<&'static str> {
+    include_str!("../src/arch/x86.rs")
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("@FEATURE:") || line.starts_with("@BIND_FEATURE_NAME:"))
+        .map(first_string)
+        .collect()
+}
+
+/// The names in the `FEATURES` table of `core_detect_macros`.
+fn macro_table_names() -> BTreeSet<&'static str> {
+    include_str!("../core_detect_macros/src/lib.rs")
+        .lines()
+        .skip_while(|line| !line.starts_with("const FEATURES:"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("];"))
+        .map(str::trim)
+        .filter(|line| line.starts_with("(\""))
+        .map(first_string)
+        .collect()
+}
+
+#[test]
+fn macro_feature_table_matches_features() {
+    let detectable = detectable_names();
+    let macro_table = macro_table_names();
+    // Make sure the parsing above found something.
+    assert!(detectable.contains("sse2") && detectable.contains("abm"));
+    for &name in &detectable {
+        assert!(
+            core_detect::Feature::from_name(name).is_some(),
+            "{} isn't a feature",
+            name
+        );
+    }
+    assert_eq!(
+        detectable.difference(&macro_table).collect::<Vec<_>>(),
+        Vec::<&&str>::new(),
+        "missing from core_detect_macros' FEATURES table"
+    );
+    assert_eq!(
+        macro_table.difference(&detectable).collect::<Vec<_>>(),
+        Vec::<&&str>::new(),
+        "in core_detect_macros' FEATURES table, but not a feature"
+    );
+}
@@ -0,0 +1,30 @@
+#![cfg(all(feature = "macros", any(target_arch = "x86", target_arch = "x86_64")))]
+
+#[core_detect::attr::multiversion(targets("x86-64-v4", "x86-64-v3", "avx2+fma", "sse4.1"))]
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[core_detect::attr::multiversion(targets("popcnt"))]
+unsafe fn count_ones(mut ptr: *const u64, len: usize) -> u32 {
+    let mut total = 0;
+    for _ in 0..len {
+        total += (*ptr).count_ones();
+        ptr = ptr.add(1);
+    }
+    total
+}
+
+// These names differ from the ones `#[target_feature]` uses.
+#[core_detect::attr::multiversion(targets("avx512vaes+avx512gfni+avx512f", "abm"))]
+fn xor(a: u64, b: u64) -> u64 {
+    a ^ b
+}
+
+#[test]
+fn multiversion_attr() {
+    assert_eq!(dot(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]), 32.0);
+    let xs = [1u64, 3, !0];
+    assert_eq!(unsafe { count_ones(xs.as_ptr(), xs.len()) }, 67);
+    assert_eq!(xor(0xf0, 0xff), 0x0f);
+}
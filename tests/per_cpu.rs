@@ -0,0 +1,41 @@
+#[test]
+#[cfg(all(
+    feature = "linux_syscalls",
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "x86"),
+))]
+fn detect_features_per_cpu() {
+    let mut per_cpu = Vec::new();
+    let result = core_detect::detect_features_per_cpu(|_, features| {
+        assert_eq!(
+            features.contains("sse2"),
+            core_detect::is_x86_feature_detected!("sse2"),
+        );
+        per_cpu.push(features);
+    })
+    .unwrap();
+    assert_eq!(result.cpu_count(), per_cpu.len());
+    assert!(result.common().intersection(&result.differing()).is_empty());
+    for features in &per_cpu {
+        assert!(result.common().difference(features).is_empty());
+    }
+
+    // The global cache was filled on one of these CPUs, so it has at least
+    // the common features, and exactly those unless some CPUs differ.
+    macro_rules! check_global {
+        ($($feature:tt),*) => {$(
+            let detected = core_detect::is_x86_feature_detected!($feature);
+            if result.common().contains($feature) {
+                assert!(detected, "{} is common, but not detected", $feature);
+            } else if result.differing().is_empty() {
+                assert!(!detected, "{} is detected, but not common", $feature);
+            }
+        )*};
+    }
+    check_global!("sse2", "sse4.2", "popcnt", "aes", "avx", "avx2", "bmi2", "fma", "avx512f");
+    let global = core_detect::snapshot();
+    assert!(result.common().difference(&global).is_empty());
+    if result.differing().is_empty() {
+        assert_eq!(result.common(), global);
+    }
+}